@@ -0,0 +1,561 @@
+//! SQL 自动补全
+//!
+//! 连接后首次补全时读取目录（模式、表、列、函数）并缓存，执行 DDL 后失效。
+//! 补全只做词法层面的分析：根据光标前的关键字判断上下文，
+//! 从 FROM / JOIN 子句中收集表和别名，用于 `u.` 之后补全列名。
+
+use crate::lexer::{self, Token, TokenKind, KEYWORDS};
+use serde::Serialize;
+use std::collections::HashMap;
+use tokio_postgres::Client;
+
+/// 单次补全最多返回的候选数
+const MAX_ITEMS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompletionKind {
+    Alias,
+    Column,
+    Table,
+    View,
+    Schema,
+    Function,
+    Keyword,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CompletionItem {
+    pub label: String,
+    pub kind: CompletionKind,
+    /// 列类型、表所在模式等补充说明
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Completions {
+    /// 被替换的前缀起始位置（字符偏移），前端用 [from, cursor) 替换为候选
+    pub from: usize,
+    pub items: Vec<CompletionItem>,
+}
+
+struct Relation {
+    schema: String,
+    name: String,
+    is_view: bool,
+}
+
+struct Column {
+    name: String,
+    data_type: String,
+}
+
+/// 当前连接的数据库目录
+pub struct Catalog {
+    schemas: Vec<String>,
+    relations: Vec<Relation>,
+    /// 键为 (模式, 表名)
+    columns: HashMap<(String, String), Vec<Column>>,
+    /// (模式, 函数名)，重载的函数只保留一个
+    functions: Vec<(String, String)>,
+}
+
+impl Catalog {
+    pub async fn load(client: &Client) -> Result<Catalog, tokio_postgres::Error> {
+        let schemas = client
+            .query(
+                "SELECT nspname FROM pg_namespace
+                 WHERE nspname NOT LIKE 'pg\\_%' AND nspname <> 'information_schema'
+                 ORDER BY nspname",
+                &[],
+            )
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        let relations = client
+            .query(
+                "SELECT n.nspname, c.relname, c.relkind IN ('v', 'm')
+                 FROM pg_class c JOIN pg_namespace n ON n.oid = c.relnamespace
+                 WHERE c.relkind IN ('r', 'p', 'v', 'm', 'f')
+                   AND n.nspname NOT LIKE 'pg\\_%' AND n.nspname <> 'information_schema'
+                 ORDER BY n.nspname, c.relname",
+                &[],
+            )
+            .await?
+            .iter()
+            .map(|row| Relation {
+                schema: row.get(0),
+                name: row.get(1),
+                is_view: row.get(2),
+            })
+            .collect();
+
+        let mut columns: HashMap<(String, String), Vec<Column>> = HashMap::new();
+        for row in client
+            .query(
+                "SELECT n.nspname, c.relname, a.attname, format_type(a.atttypid, a.atttypmod)
+                 FROM pg_attribute a
+                 JOIN pg_class c ON c.oid = a.attrelid
+                 JOIN pg_namespace n ON n.oid = c.relnamespace
+                 WHERE a.attnum > 0 AND NOT a.attisdropped
+                   AND c.relkind IN ('r', 'p', 'v', 'm', 'f')
+                   AND n.nspname NOT LIKE 'pg\\_%' AND n.nspname <> 'information_schema'
+                 ORDER BY n.nspname, c.relname, a.attnum",
+                &[],
+            )
+            .await?
+        {
+            columns
+                .entry((row.get(0), row.get(1)))
+                .or_default()
+                .push(Column {
+                    name: row.get(2),
+                    data_type: row.get(3),
+                });
+        }
+
+        // 系统函数也在候选之列，如 now()、jsonb_build_object()
+        let functions = client
+            .query(
+                "SELECT DISTINCT n.nspname, p.proname
+                 FROM pg_proc p JOIN pg_namespace n ON n.oid = p.pronamespace
+                 WHERE n.nspname <> 'information_schema' AND p.proname NOT LIKE '\\_%'
+                 ORDER BY p.proname",
+                &[],
+            )
+            .await?
+            .iter()
+            .map(|row| (row.get(0), row.get(1)))
+            .collect();
+
+        Ok(Catalog {
+            schemas,
+            relations,
+            columns,
+            functions,
+        })
+    }
+
+    /// 按名称查找表，未指定模式时优先 public
+    fn find_relation(&self, schema: Option<&str>, name: &str) -> Option<&Relation> {
+        let mut candidates = self.relations.iter().filter(|r| {
+            r.name == name
+                && match schema {
+                    Some(schema) => r.schema == schema,
+                    None => true,
+                }
+        });
+        let first = candidates.next()?;
+        if first.schema == "public" || schema.is_some() {
+            return Some(first);
+        }
+        Some(candidates.find(|r| r.schema == "public").unwrap_or(first))
+    }
+
+    fn relation_columns(&self, relation: &Relation) -> &[Column] {
+        self.columns
+            .get(&(relation.schema.clone(), relation.name.clone()))
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+}
+
+/// FROM / JOIN 中出现的表及其别名
+struct TableRef {
+    schema: Option<String>,
+    name: String,
+    alias: Option<String>,
+}
+
+/// 执行的 SQL 是否可能改变表结构，是则需要刷新目录缓存
+pub fn changes_schema(sql: &str) -> bool {
+    lexer::split_statements(sql).iter().any(|statement| {
+        let first = lexer::tokenize(statement.text)
+            .into_iter()
+            .find(|t| !t.is_trivia())
+            .map(|t| t.text.to_uppercase());
+        matches!(
+            first.as_deref(),
+            Some("CREATE") | Some("ALTER") | Some("DROP") | Some("DO") | Some("IMPORT")
+        )
+    })
+}
+
+/// 计算光标处的补全候选，`cursor` 为字符偏移
+pub fn complete(catalog: &Catalog, text: &str, cursor: usize) -> Completions {
+    let cursor_byte = text
+        .char_indices()
+        .nth(cursor)
+        .map(|(i, _)| i)
+        .unwrap_or(text.len());
+    let to_chars = |byte: usize| text[..byte].chars().count();
+
+    // 光标所在的语句，光标在两条语句之间时取前一条之后的空白部分
+    let (statement_start, statement_end) = lexer::split_statements(text)
+        .iter()
+        .rev()
+        .find(|s| s.offset <= cursor_byte)
+        .map(|s| (s.offset, s.offset + s.text.len()))
+        .unwrap_or((0, 0));
+    let statement_start = match text[statement_end..cursor_byte.max(statement_end)].find(';') {
+        Some(i) => statement_end + i + 1,
+        None => statement_start,
+    };
+    let statement = &text[statement_start..statement_end.max(cursor_byte)];
+    let local_cursor = cursor_byte - statement_start;
+
+    let tokens: Vec<Token> = lexer::tokenize(statement);
+    let before: Vec<&Token> = tokens.iter().filter(|t| t.offset < local_cursor).collect();
+
+    // 光标前正在输入的词
+    let (prefix, prefix_start) = match before.last() {
+        Some(t) if t.offset + t.text.len() >= local_cursor => match t.kind {
+            TokenKind::Word => (&t.text[..local_cursor - t.offset], t.offset),
+            TokenKind::QuotedIdent => (
+                t.text[1..local_cursor - t.offset].trim_end_matches('"'),
+                t.offset,
+            ),
+            // 字符串、注释中不补全
+            TokenKind::String
+            | TokenKind::DollarString
+            | TokenKind::LineComment
+            | TokenKind::BlockComment => {
+                return Completions {
+                    from: cursor,
+                    items: Vec::new(),
+                }
+            }
+            _ => ("", local_cursor),
+        },
+        _ => ("", local_cursor),
+    };
+    let from = to_chars(statement_start + prefix_start);
+
+    let significant: Vec<&Token> = before
+        .iter()
+        .copied()
+        .filter(|t| !t.is_trivia() && t.offset < prefix_start)
+        .collect();
+
+    let tables = collect_tables(&tokens);
+    let mut items = Vec::new();
+
+    // `限定名.` 之后：别名或表的列，模式下的表和函数
+    if let [.., qualifier, dot] = significant.as_slice() {
+        if dot.text == "." && matches!(qualifier.kind, TokenKind::Word | TokenKind::QuotedIdent) {
+            let qualifier = ident_name(qualifier);
+            qualified_items(catalog, &tables, &qualifier, &mut items);
+            return finish(items, prefix, from);
+        }
+    }
+
+    let keyword = significant
+        .iter()
+        .rev()
+        .find(|t| t.kind == TokenKind::Word && KEYWORDS.contains(&t.text.to_uppercase().as_str()))
+        .map(|t| t.text.to_uppercase());
+
+    match keyword.as_deref() {
+        Some("FROM") | Some("JOIN") | Some("INTO") | Some("UPDATE") | Some("TABLE") => {
+            relation_items(catalog, &mut items);
+        }
+        None => {}
+        _ => {
+            for table in &tables {
+                if let Some(alias) = &table.alias {
+                    items.push(CompletionItem {
+                        label: alias.clone(),
+                        kind: CompletionKind::Alias,
+                        detail: Some(table.name.clone()),
+                    });
+                }
+                if let Some(relation) = catalog.find_relation(table.schema.as_deref(), &table.name)
+                {
+                    column_items(catalog, relation, &mut items);
+                }
+            }
+            for (schema, name) in &catalog.functions {
+                items.push(CompletionItem {
+                    label: name.clone(),
+                    kind: CompletionKind::Function,
+                    detail: Some(schema.clone()),
+                });
+            }
+        }
+    }
+
+    items.extend(KEYWORDS.iter().map(|k| CompletionItem {
+        label: k.to_string(),
+        kind: CompletionKind::Keyword,
+        detail: None,
+    }));
+
+    finish(items, prefix, from)
+}
+
+fn qualified_items(
+    catalog: &Catalog,
+    tables: &[TableRef],
+    qualifier: &str,
+    items: &mut Vec<CompletionItem>,
+) {
+    let aliased = tables
+        .iter()
+        .find(|t| t.alias.as_deref() == Some(qualifier))
+        .or_else(|| tables.iter().find(|t| t.name == qualifier));
+    let relation = match aliased {
+        Some(table) => catalog.find_relation(table.schema.as_deref(), &table.name),
+        None => catalog.find_relation(None, qualifier),
+    };
+    if let Some(relation) = relation {
+        column_items(catalog, relation, items);
+    }
+
+    if catalog.schemas.iter().any(|s| s == qualifier) {
+        for relation in catalog.relations.iter().filter(|r| r.schema == qualifier) {
+            items.push(relation_item(relation));
+        }
+        for (_, name) in catalog.functions.iter().filter(|(s, _)| s == qualifier) {
+            items.push(CompletionItem {
+                label: name.clone(),
+                kind: CompletionKind::Function,
+                detail: Some(qualifier.to_string()),
+            });
+        }
+    }
+}
+
+fn relation_items(catalog: &Catalog, items: &mut Vec<CompletionItem>) {
+    items.extend(catalog.relations.iter().map(relation_item));
+    items.extend(catalog.schemas.iter().map(|schema| CompletionItem {
+        label: schema.clone(),
+        kind: CompletionKind::Schema,
+        detail: None,
+    }));
+}
+
+fn relation_item(relation: &Relation) -> CompletionItem {
+    CompletionItem {
+        label: relation.name.clone(),
+        kind: if relation.is_view {
+            CompletionKind::View
+        } else {
+            CompletionKind::Table
+        },
+        detail: Some(relation.schema.clone()),
+    }
+}
+
+fn column_items(catalog: &Catalog, relation: &Relation, items: &mut Vec<CompletionItem>) {
+    items.extend(
+        catalog
+            .relation_columns(relation)
+            .iter()
+            .map(|column| CompletionItem {
+                label: column.name.clone(),
+                kind: CompletionKind::Column,
+                detail: Some(format!("{}.{}", relation.name, column.data_type)),
+            }),
+    );
+}
+
+/// 按前缀过滤（不区分大小写）、去重、排序
+fn finish(mut items: Vec<CompletionItem>, prefix: &str, from: usize) -> Completions {
+    let prefix = prefix.to_lowercase();
+    items.retain(|item| item.label.to_lowercase().starts_with(&prefix));
+    items.sort_by(|a, b| a.kind.cmp(&b.kind).then_with(|| a.label.cmp(&b.label)));
+    items.dedup_by(|a, b| a.kind == b.kind && a.label == b.label);
+    items.truncate(MAX_ITEMS);
+    Completions { from, items }
+}
+
+/// 标识符的实际名称：带引号的去掉引号，不带引号的转为小写
+fn ident_name(token: &Token) -> String {
+    match token.kind {
+        TokenKind::QuotedIdent => {
+            token.text[1..token.text.len().saturating_sub(1).max(1)].replace("\"\"", "\"")
+        }
+        _ => token.text.to_lowercase(),
+    }
+}
+
+/// 从整条语句的 FROM / JOIN / UPDATE / INTO 子句中收集表引用
+fn collect_tables(tokens: &[Token]) -> Vec<TableRef> {
+    let significant: Vec<&Token> = tokens.iter().filter(|t| !t.is_trivia()).collect();
+    let mut tables = Vec::new();
+    let mut i = 0;
+
+    while i < significant.len() {
+        let word = significant[i].text.to_uppercase();
+        if !matches!(word.as_str(), "FROM" | "JOIN" | "UPDATE" | "INTO") {
+            i += 1;
+            continue;
+        }
+        i += 1;
+        // FROM a, b 中逗号分隔的多个表
+        while let Some(table) = parse_table_ref(&significant, &mut i) {
+            tables.push(table);
+            if significant.get(i).is_some_and(|t| t.text == ",") {
+                i += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    tables
+}
+
+/// 可作为表名或别名的词：带引号的标识符或非关键字
+fn is_name(token: &Token) -> bool {
+    token.kind == TokenKind::QuotedIdent
+        || (token.kind == TokenKind::Word
+            && !KEYWORDS.contains(&token.text.to_uppercase().as_str()))
+}
+
+fn parse_table_ref(tokens: &[&Token], i: &mut usize) -> Option<TableRef> {
+    // ONLY、LATERAL 等修饰词
+    while tokens
+        .get(*i)
+        .is_some_and(|t| matches!(t.text.to_uppercase().as_str(), "ONLY" | "LATERAL"))
+    {
+        *i += 1;
+    }
+
+    let first = tokens.get(*i).copied().filter(|t| is_name(t))?;
+    *i += 1;
+    let (schema, name) = if tokens.get(*i).is_some_and(|t| t.text == ".") {
+        let second = tokens.get(*i + 1).copied().filter(|t| is_name(t))?;
+        *i += 2;
+        (Some(ident_name(first)), ident_name(second))
+    } else {
+        (None, ident_name(first))
+    };
+
+    // 表函数调用不是表
+    if tokens.get(*i).is_some_and(|t| t.text == "(") {
+        return None;
+    }
+
+    if tokens
+        .get(*i)
+        .is_some_and(|t| t.text.eq_ignore_ascii_case("AS"))
+    {
+        *i += 1;
+    }
+    let alias = tokens.get(*i).copied().filter(|t| is_name(t)).map(|t| {
+        *i += 1;
+        ident_name(t)
+    });
+
+    Some(TableRef {
+        schema,
+        name,
+        alias,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog() -> Catalog {
+        let relation = |schema: &str, name: &str, is_view| Relation {
+            schema: schema.to_string(),
+            name: name.to_string(),
+            is_view,
+        };
+        let columns = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| Column {
+                    name: name.to_string(),
+                    data_type: "integer".to_string(),
+                })
+                .collect()
+        };
+        let mut all = HashMap::new();
+        all.insert(
+            ("public".to_string(), "users".to_string()),
+            columns(&["id", "email"]),
+        );
+        all.insert(
+            ("sales".to_string(), "orders".to_string()),
+            columns(&["id", "user_id", "total"]),
+        );
+        Catalog {
+            schemas: vec!["public".to_string(), "sales".to_string()],
+            relations: vec![
+                relation("public", "users", false),
+                relation("public", "active_users", true),
+                relation("sales", "orders", false),
+            ],
+            columns: all,
+            functions: vec![("pg_catalog".to_string(), "upper".to_string())],
+        }
+    }
+
+    /// 光标位置用 `|` 标出
+    fn labels(text: &str, kind: CompletionKind) -> (usize, Vec<String>) {
+        let cursor = text.chars().position(|c| c == '|').unwrap();
+        let completions = complete(&catalog(), &text.replace('|', ""), cursor);
+        let labels = completions
+            .items
+            .into_iter()
+            .filter(|item| item.kind == kind)
+            .map(|item| item.label)
+            .collect();
+        (completions.from, labels)
+    }
+
+    #[test]
+    fn tables_after_from() {
+        let (from, tables) = labels("SELECT * FROM us|", CompletionKind::Table);
+        assert_eq!(from, 14);
+        assert_eq!(tables, ["users"]);
+        let (_, views) = labels("SELECT * FROM |", CompletionKind::View);
+        assert_eq!(views, ["active_users"]);
+    }
+
+    #[test]
+    fn columns_of_aliased_and_qualified_tables() {
+        let (_, columns) = labels(
+            "SELECT o.| FROM sales.orders AS o JOIN users u ON u.id = o.user_id",
+            CompletionKind::Column,
+        );
+        assert_eq!(columns, ["id", "total", "user_id"]);
+
+        let (_, columns) = labels("SELECT e| FROM users", CompletionKind::Column);
+        assert_eq!(columns, ["email"]);
+    }
+
+    #[test]
+    fn schema_members_after_dot() {
+        let (_, tables) = labels("SELECT * FROM sales.|", CompletionKind::Table);
+        assert_eq!(tables, ["orders"]);
+    }
+
+    #[test]
+    fn nothing_inside_strings_and_comments() {
+        assert!(complete(&catalog(), "SELECT 'us", 10).items.is_empty());
+        assert!(complete(&catalog(), "SELECT 1 -- us", 14).items.is_empty());
+    }
+
+    #[test]
+    fn uses_the_statement_under_the_cursor() {
+        let (_, columns) = labels(
+            "SELECT * FROM sales.orders; SELECT | FROM users",
+            CompletionKind::Column,
+        );
+        assert_eq!(columns, ["email", "id"]);
+    }
+
+    #[test]
+    fn detects_schema_changes() {
+        assert!(changes_schema("SELECT 1; create table t (id int)"));
+        assert!(!changes_schema("SELECT 'create table'; -- drop"));
+    }
+}
@@ -0,0 +1,286 @@
+use crate::error::QueryError;
+use crate::ident::{quote_ident, quote_literal, quote_qualified};
+use bytes::Bytes;
+use futures_util::{pin_mut, SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
+use tokio_postgres::Client;
+
+/// COPY 进度事件名
+pub const COPY_PROGRESS_EVENT: &str = "copy-progress";
+
+/// 每次从文件读取的字节数
+const CHUNK_SIZE: usize = 64 * 1024;
+/// 每传输这么多字节发送一次进度
+const PROGRESS_INTERVAL: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CopyFormat {
+    #[default]
+    Csv,
+    Text,
+    Binary,
+}
+
+impl CopyFormat {
+    fn as_sql(self) -> &'static str {
+        match self {
+            CopyFormat::Csv => "csv",
+            CopyFormat::Text => "text",
+            CopyFormat::Binary => "binary",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CopyOptions {
+    /// 本地文件路径
+    pub path: String,
+    /// 目标表，可带 schema 前缀
+    pub table: Option<String>,
+    /// 导出时可以用查询代替表
+    pub query: Option<String>,
+    pub columns: Option<Vec<String>>,
+    #[serde(default)]
+    pub format: CopyFormat,
+    pub header: Option<bool>,
+    pub delimiter: Option<String>,
+    /// 表示 NULL 的字符串
+    pub null: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CopyDirection {
+    In,
+    Out,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CopyProgress {
+    pub direction: CopyDirection,
+    pub path: String,
+    pub bytes: u64,
+    /// 导入时为文件大小
+    pub total_bytes: Option<u64>,
+    /// 已传输的行数，按换行符估算；binary 格式为 None
+    pub rows: Option<u64>,
+    pub done: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CopySummary {
+    pub direction: CopyDirection,
+    pub path: String,
+    pub sql: String,
+    pub bytes: u64,
+    pub rows: Option<u64>,
+    pub duration_ms: u64,
+}
+
+fn build_copy_sql(options: &CopyOptions, direction: CopyDirection) -> Result<String, QueryError> {
+    let target = match (direction, &options.query, &options.table) {
+        (CopyDirection::Out, Some(query), _) => {
+            if options.columns.is_some() {
+                return Err("使用 query 导出时不能指定 columns".into());
+            }
+            format!("({})", query.trim().trim_end_matches(';'))
+        }
+        (_, _, Some(table)) => {
+            let mut target = quote_qualified(table);
+            if let Some(columns) = &options.columns {
+                let columns: Vec<String> = columns.iter().map(|c| quote_ident(c)).collect();
+                target.push_str(&format!(" ({})", columns.join(", ")));
+            }
+            target
+        }
+        (CopyDirection::In, _, None) => return Err("导入必须指定 table".into()),
+        (CopyDirection::Out, None, None) => return Err("导出必须指定 table 或 query".into()),
+    };
+
+    let mut with = vec![format!("FORMAT {}", options.format.as_sql())];
+    if let Some(header) = options.header {
+        with.push(format!("HEADER {}", header));
+    }
+    if let Some(delimiter) = &options.delimiter {
+        with.push(format!("DELIMITER {}", quote_literal(delimiter)));
+    }
+    if let Some(null) = &options.null {
+        with.push(format!("NULL {}", quote_literal(null)));
+    }
+
+    let endpoint = match direction {
+        CopyDirection::In => "FROM STDIN",
+        CopyDirection::Out => "TO STDOUT",
+    };
+    Ok(format!(
+        "COPY {} {} WITH ({})",
+        target,
+        endpoint,
+        with.join(", ")
+    ))
+}
+
+fn count_lines(chunk: &[u8]) -> u64 {
+    chunk.iter().filter(|&&b| b == b'\n').count() as u64
+}
+
+/// 将本地文件通过 `COPY ... FROM STDIN` 流式导入表中
+pub async fn copy_in_file(
+    client: &Client,
+    options: &CopyOptions,
+    mut on_progress: impl FnMut(CopyProgress),
+) -> Result<CopySummary, QueryError> {
+    let sql = build_copy_sql(options, CopyDirection::In)?;
+    let started = Instant::now();
+
+    let mut file = File::open(&options.path)
+        .await
+        .map_err(|e| format!("打开文件失败: {}", e))?;
+    let total_bytes = file.metadata().await.ok().map(|m| m.len());
+
+    let sink = client
+        .copy_in::<_, Bytes>(&sql)
+        .await
+        .map_err(|e| QueryError::from_pg("COPY 导入失败", &e))?;
+    pin_mut!(sink);
+
+    let count_rows = options.format != CopyFormat::Binary;
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut bytes = 0u64;
+    let mut lines = 0u64;
+    let mut reported = 0u64;
+
+    loop {
+        // 中途返回错误时 sink 未 finish 便被丢弃，服务器端的 COPY 会被中止
+        let n = file
+            .read(&mut buf)
+            .await
+            .map_err(|e| format!("读取文件失败: {}", e))?;
+        if n == 0 {
+            break;
+        }
+        if count_rows {
+            lines += count_lines(&buf[..n]);
+        }
+        sink.send(Bytes::copy_from_slice(&buf[..n]))
+            .await
+            .map_err(|e| QueryError::from_pg("COPY 导入失败", &e))?;
+
+        bytes += n as u64;
+        if bytes - reported >= PROGRESS_INTERVAL {
+            reported = bytes;
+            on_progress(CopyProgress {
+                direction: CopyDirection::In,
+                path: options.path.clone(),
+                bytes,
+                total_bytes,
+                rows: count_rows.then_some(lines),
+                done: false,
+            });
+        }
+    }
+
+    let rows = sink
+        .as_mut()
+        .finish()
+        .await
+        .map_err(|e| QueryError::from_pg("COPY 导入失败", &e))?;
+
+    on_progress(CopyProgress {
+        direction: CopyDirection::In,
+        path: options.path.clone(),
+        bytes,
+        total_bytes,
+        rows: Some(rows),
+        done: true,
+    });
+
+    Ok(CopySummary {
+        direction: CopyDirection::In,
+        path: options.path.clone(),
+        sql,
+        bytes,
+        rows: Some(rows),
+        duration_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+/// 通过 `COPY ... TO STDOUT` 将表或查询结果流式导出到本地文件
+pub async fn copy_out_file(
+    client: &Client,
+    options: &CopyOptions,
+    mut on_progress: impl FnMut(CopyProgress),
+) -> Result<CopySummary, QueryError> {
+    let sql = build_copy_sql(options, CopyDirection::Out)?;
+    let started = Instant::now();
+
+    let stream = client
+        .copy_out(&sql)
+        .await
+        .map_err(|e| QueryError::from_pg("COPY 导出失败", &e))?;
+    pin_mut!(stream);
+
+    let file = File::create(&options.path)
+        .await
+        .map_err(|e| format!("创建文件失败: {}", e))?;
+    let mut writer = BufWriter::new(file);
+
+    let count_rows = options.format != CopyFormat::Binary;
+    let mut bytes = 0u64;
+    let mut lines = 0u64;
+    let mut reported = 0u64;
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| QueryError::from_pg("COPY 导出失败", &e))?;
+        writer
+            .write_all(&chunk)
+            .await
+            .map_err(|e| format!("写入文件失败: {}", e))?;
+        if count_rows {
+            lines += count_lines(&chunk);
+        }
+
+        bytes += chunk.len() as u64;
+        if bytes - reported >= PROGRESS_INTERVAL {
+            reported = bytes;
+            on_progress(CopyProgress {
+                direction: CopyDirection::Out,
+                path: options.path.clone(),
+                bytes,
+                total_bytes: None,
+                rows: count_rows.then_some(lines),
+                done: false,
+            });
+        }
+    }
+    writer
+        .flush()
+        .await
+        .map_err(|e| format!("写入文件失败: {}", e))?;
+
+    // CSV 表头占一行，不计入数据行数
+    let header_lines = u64::from(options.format == CopyFormat::Csv && options.header == Some(true));
+    let rows = count_rows.then(|| lines.saturating_sub(header_lines));
+
+    on_progress(CopyProgress {
+        direction: CopyDirection::Out,
+        path: options.path.clone(),
+        bytes,
+        total_bytes: None,
+        rows,
+        done: true,
+    });
+
+    Ok(CopySummary {
+        direction: CopyDirection::Out,
+        path: options.path.clone(),
+        sql,
+        bytes,
+        rows,
+        duration_ms: started.elapsed().as_millis() as u64,
+    })
+}
@@ -0,0 +1,712 @@
+//! 聚合管道
+//!
+//! 每个阶段编译为一个 CTE（`stage_1`、`stage_2` ...），后一阶段从前一阶段读取，
+//! 最后 `SELECT * FROM` 最后一个阶段。每个阶段结束后记录输出的列，
+//! 后续阶段的字段名按这些列校验。CTE 中的 ORDER BY 不保证外层查询的顺序，
+//! 最后一个阶段是 `$sort` 时在外层再排序一次。
+
+use super::find::{flag_value, order_by, select_list};
+use super::{
+    build_condition, query, resolve_relation, tidy_sql, Field, Params, QueryResult, Relation,
+    RelationColumn,
+};
+use crate::error::QueryError;
+use crate::ident::quote_ident;
+use crate::stmt_cache::StatementCache;
+use tokio_postgres::Client;
+
+/// `{"operation": "aggregate", "pipeline": [{"$match": {...}}, {"$group": {...}}, ...]}`
+pub async fn aggregate(
+    client: &Client,
+    cache: &StatementCache,
+    relation: &Relation,
+    request: &serde_json::Value,
+) -> Result<serde_json::Value, QueryError> {
+    let stages = request
+        .get("pipeline")
+        .and_then(|v| v.as_array())
+        .ok_or("aggregate 需要 pipeline 数组")?;
+
+    let mut pipeline = Pipeline::new(relation);
+    let mut stages = stages.iter().map(stage_of).peekable();
+    while let Some(stage) = stages.next() {
+        let (name, spec) = stage?;
+        match name {
+            "$match" => pipeline.match_stage(spec)?,
+            "$group" => pipeline.group(spec)?,
+            "$project" => pipeline.project(spec)?,
+            "$sort" | "$skip" | "$limit" => {
+                // 紧随其后的 $skip、$limit 并入同一阶段，分页作用在排序后的结果上
+                let mut tail = Tail::default();
+                tail.set(name, spec)?;
+                while let Some(Ok((next, spec))) = stages.peek() {
+                    if !tail.accepts(next) {
+                        break;
+                    }
+                    tail.set(next, spec)?;
+                    stages.next();
+                }
+                pipeline.tail(&tail)?;
+            }
+            "$unwind" => pipeline.unwind(spec)?,
+            "$lookup" => {
+                let from = spec
+                    .get("from")
+                    .and_then(|v| v.as_str())
+                    .ok_or("$lookup 需要 from")?;
+                let foreign = resolve_relation(client, cache, None, from).await?;
+                pipeline.lookup(spec, &foreign)?;
+            }
+            _ => return Err(format!("不支持的聚合阶段: {}", name).into()),
+        }
+    }
+
+    let sql = pipeline.sql();
+    let rows = query(client, cache, &sql, &pipeline.params)
+        .await
+        .map_err(|mut e| {
+            e.context = Some("聚合失败".to_string());
+            e
+        })?;
+    QueryResult {
+        data: crate::rows_to_json(&rows),
+        sql: Some(tidy_sql(&sql)),
+        ..Default::default()
+    }
+    .into_value()
+}
+
+/// 每个阶段都是只有一个键的对象，如 `{"$match": {...}}`
+fn stage_of(stage: &serde_json::Value) -> Result<(&str, &serde_json::Value), QueryError> {
+    match stage.as_object() {
+        Some(object) if object.len() == 1 => {
+            let (name, spec) = object.iter().next().unwrap();
+            Ok((name.as_str(), spec))
+        }
+        _ => Err("pipeline 的每个阶段必须是只有一个键的对象".into()),
+    }
+}
+
+/// 引用字段的写法 `"$amount"`、`"$payload.user.id"`
+fn field_ref<'r>(
+    relation: &'r Relation,
+    value: &serde_json::Value,
+    context: &str,
+) -> Result<Field<'r>, QueryError> {
+    let name = value
+        .as_str()
+        .and_then(|s| s.strip_prefix('$'))
+        .ok_or_else(|| format!("{} 必须是 \"$字段\" 形式", context))?;
+    relation.field(name)
+}
+
+/// 字段的取值表达式及其类型，路径保持 jsonb
+fn value_of(field: &Field, params: &mut Params) -> (String, String) {
+    let expr = if field.path.is_empty() {
+        quote_ident(&field.column.name)
+    } else {
+        field.jsonb_expr(params)
+    };
+    (expr, type_of(field))
+}
+
+/// 字段取值的类型，不生成表达式
+fn type_of(field: &Field) -> String {
+    if field.path.is_empty() {
+        field.column.data_type.clone()
+    } else {
+        "jsonb".to_string()
+    }
+}
+
+struct Pipeline<'a> {
+    source: &'a Relation,
+    ctes: Vec<String>,
+    /// 当前阶段的输出
+    current: Relation,
+    params: Params,
+    /// 最后一个阶段是 `$sort` 时的排序，外层查询保持同样的顺序
+    order_by: String,
+}
+
+impl<'a> Pipeline<'a> {
+    fn new(source: &'a Relation) -> Self {
+        Pipeline {
+            source,
+            ctes: Vec::new(),
+            current: stage_relation(source, 0, source.columns.clone()),
+            params: Params::default(),
+            order_by: String::new(),
+        }
+    }
+
+    /// 当前阶段在 SQL 中的名称
+    fn from(&self) -> String {
+        if self.ctes.is_empty() {
+            self.source.qualified()
+        } else {
+            format!("stage_{}", self.ctes.len())
+        }
+    }
+
+    fn push(&mut self, select: String, columns: Vec<RelationColumn>) {
+        self.ctes
+            .push(format!("stage_{} AS ({})", self.ctes.len() + 1, select));
+        self.current = stage_relation(self.source, self.ctes.len(), columns);
+        self.order_by.clear();
+    }
+
+    fn sql(&self) -> String {
+        if self.ctes.is_empty() {
+            format!("SELECT * FROM {}", self.from())
+        } else {
+            format!(
+                "WITH {} SELECT * FROM {}{}",
+                self.ctes.join(", "),
+                self.from(),
+                self.order_by
+            )
+        }
+    }
+
+    /// `{"$match": {...}}`，与 find 的 filter 相同
+    fn match_stage(&mut self, spec: &serde_json::Value) -> Result<(), QueryError> {
+        let filter = spec.as_object().ok_or("$match 必须是对象")?;
+        let Some(condition) = build_condition(&self.current, filter, &mut self.params)? else {
+            return Ok(());
+        };
+        let select = format!("SELECT * FROM {} WHERE {}", self.from(), condition);
+        let columns = self.current.columns.clone();
+        self.push(select, columns);
+        Ok(())
+    }
+
+    /// `{"$group": {"_id": "$city", "total": {"$sum": "$amount"}, "n": {"$count": {}}}}`
+    ///
+    /// `_id` 可以是单个字段、`null`（整体聚合）或 `{"city": "$city", ...}`（按多个字段分组，
+    /// 结果为 jsonb 对象）。
+    fn group(&mut self, spec: &serde_json::Value) -> Result<(), QueryError> {
+        let spec = spec.as_object().ok_or("$group 必须是对象")?;
+        let id = spec.get("_id").ok_or("$group 需要 _id")?;
+
+        let mut keys = Vec::new();
+        let (id_expr, id_type) = match id {
+            serde_json::Value::Null => ("NULL".to_string(), "text".to_string()),
+            serde_json::Value::Object(fields) => {
+                let mut pairs = Vec::with_capacity(fields.len());
+                for (name, value) in fields {
+                    let field = field_ref(&self.current, value, &format!("_id.{}", name))?;
+                    let (expr, _) = value_of(&field, &mut self.params);
+                    pairs.push(format!("{}, {}", self.params.push_key(name), expr));
+                    keys.push(expr);
+                }
+                (
+                    format!("jsonb_build_object({})", pairs.join(", ")),
+                    "jsonb".to_string(),
+                )
+            }
+            _ => {
+                let field = field_ref(&self.current, id, "_id")?;
+                let (expr, data_type) = value_of(&field, &mut self.params);
+                keys.push(expr.clone());
+                (expr, data_type)
+            }
+        };
+
+        let mut select = vec![format!("{} AS \"_id\"", id_expr)];
+        let mut columns = vec![RelationColumn {
+            name: "_id".to_string(),
+            data_type: id_type,
+        }];
+        for (name, accumulator) in spec.iter().filter(|(name, _)| *name != "_id") {
+            let (expr, data_type) = self.accumulator(name, accumulator)?;
+            select.push(format!("{} AS {}", expr, quote_ident(name)));
+            columns.push(RelationColumn {
+                name: name.clone(),
+                data_type,
+            });
+        }
+
+        let mut sql = format!("SELECT {} FROM {}", select.join(", "), self.from());
+        if !keys.is_empty() {
+            sql.push_str(&format!(" GROUP BY {}", keys.join(", ")));
+        }
+        self.push(sql, columns);
+        Ok(())
+    }
+
+    /// 累加器，返回表达式和结果类型
+    fn accumulator(
+        &mut self,
+        name: &str,
+        accumulator: &serde_json::Value,
+    ) -> Result<(String, String), QueryError> {
+        let (operator, operand) = accumulator
+            .as_object()
+            .filter(|o| o.len() == 1)
+            .and_then(|o| o.iter().next())
+            .ok_or_else(|| format!("{} 必须是 {{\"$sum\": ...}} 形式的累加器", name))?;
+        let context = format!("{} 的 {}", name, operator);
+
+        match operator.as_str() {
+            "$count" => Ok(("count(*)".to_string(), "bigint".to_string())),
+            // {"$sum": 1} 按行计数
+            "$sum" if operand.is_number() => {
+                Ok((format!("sum({})", operand), "numeric".to_string()))
+            }
+            "$sum" | "$avg" => {
+                let field = field_ref(&self.current, operand, &context)?;
+                // 路径按数值取出
+                let expr = field.expr_for(&serde_json::Value::from(0), &mut self.params);
+                Ok((
+                    format!("{}({})", &operator[1..], expr),
+                    "numeric".to_string(),
+                ))
+            }
+            "$min" | "$max" => {
+                let field = field_ref(&self.current, operand, &context)?;
+                // jsonb 没有 min/max，路径与 $sum 一样按数值取出
+                let (expr, data_type) = if field.path.is_empty() {
+                    value_of(&field, &mut self.params)
+                } else {
+                    (
+                        field.expr_for(&serde_json::Value::from(0), &mut self.params),
+                        "numeric".to_string(),
+                    )
+                };
+                Ok((format!("{}({})", &operator[1..], expr), data_type))
+            }
+            "$push" => {
+                let field = field_ref(&self.current, operand, &context)?;
+                let (expr, data_type) = value_of(&field, &mut self.params);
+                Ok((format!("array_agg({})", expr), format!("{}[]", data_type)))
+            }
+            _ => Err(format!("不支持的累加器: {}（字段 {}）", operator, name).into()),
+        }
+    }
+
+    /// `{"$project": {"name": 1, "city": "$address.city"}}`，包含、排除或改名
+    fn project(&mut self, spec: &serde_json::Value) -> Result<(), QueryError> {
+        let spec = spec.as_object().ok_or("$project 必须是对象")?;
+
+        // 改名的字段单独处理，其余与 find 的 projection 相同
+        let mut renamed = Vec::new();
+        let mut columns = Vec::new();
+        let mut flags = serde_json::Map::new();
+        for (name, value) in spec {
+            if value.is_string() {
+                let field = field_ref(&self.current, value, name)?;
+                let (expr, data_type) = value_of(&field, &mut self.params);
+                renamed.push(format!("{} AS {}", expr, quote_ident(name)));
+                columns.push(RelationColumn {
+                    name: name.clone(),
+                    data_type,
+                });
+            } else {
+                flags.insert(name.clone(), value.clone());
+            }
+        }
+
+        // 与 MongoDB 一样，`"_id": 0` 可以和包含的字段一起使用
+        let including = !renamed.is_empty() || flags.values().any(|v| flag_value(v) == Some(true));
+        if including && flags.get("_id").and_then(flag_value) == Some(false) {
+            flags.remove("_id");
+        }
+
+        let mut select = Vec::new();
+        if !flags.is_empty() || renamed.is_empty() {
+            let projection = serde_json::Value::Object(flags.clone());
+            select.push(select_list(
+                &self.current,
+                Some(&projection),
+                &mut self.params,
+            )?);
+            let mut kept = self.projected_columns(&flags)?;
+            kept.append(&mut columns);
+            columns = kept;
+        }
+        select.append(&mut renamed);
+
+        let sql = format!("SELECT {} FROM {}", select.join(", "), self.from());
+        self.push(sql, columns);
+        Ok(())
+    }
+
+    /// projection 之后保留的列，顺序与 select_list 一致
+    fn projected_columns(
+        &self,
+        flags: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<Vec<RelationColumn>, QueryError> {
+        if !flags.values().any(|v| flag_value(v) == Some(true)) {
+            return Ok(self
+                .current
+                .columns
+                .iter()
+                .filter(|c| !flags.contains_key(&c.name))
+                .cloned()
+                .collect());
+        }
+
+        let mut columns = Vec::with_capacity(flags.len());
+        for name in flags.keys() {
+            let field = self.current.field(name)?;
+            columns.push(RelationColumn {
+                name: name.clone(),
+                data_type: type_of(&field),
+            });
+        }
+        Ok(columns)
+    }
+
+    /// 合并后的 `$sort` / `$skip` / `$limit`
+    fn tail(&mut self, tail: &Tail) -> Result<(), QueryError> {
+        let order = order_by(&self.current, tail.sort.as_ref(), &mut self.params)?;
+        let mut sql = format!("SELECT * FROM {}{}", self.from(), order);
+        if let Some(limit) = tail.limit {
+            sql.push_str(&format!(" LIMIT {}", self.params.push(limit.into())));
+        }
+        if let Some(skip) = tail.skip {
+            sql.push_str(&format!(" OFFSET {}", self.params.push(skip.into())));
+        }
+        let columns = self.current.columns.clone();
+        self.push(sql, columns);
+        // 列不变，同样的排序表达式也适用于本阶段的输出
+        self.order_by = order;
+        Ok(())
+    }
+
+    /// `{"$unwind": "$tags"}` 或 `{"$unwind": {"path": "$tags", "preserveNullAndEmptyArrays": true}}`
+    ///
+    /// 数组列用 unnest 展开，json/jsonb 列用 jsonb_array_elements 展开。
+    fn unwind(&mut self, spec: &serde_json::Value) -> Result<(), QueryError> {
+        let (path, preserve) = match spec {
+            serde_json::Value::Object(options) => (
+                options.get("path").ok_or("$unwind 需要 path")?,
+                options
+                    .get("preserveNullAndEmptyArrays")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false),
+            ),
+            _ => (spec, false),
+        };
+        let field = field_ref(&self.current, path, "$unwind")?;
+        if !field.path.is_empty() {
+            return Err("$unwind 只能展开整列，请先用 $project 取出路径".into());
+        }
+        let column = field.column;
+        let (elements, element_type) = if column.is_json() {
+            let cast = if column.data_type == "json" {
+                "::jsonb"
+            } else {
+                ""
+            };
+            (
+                format!(
+                    "jsonb_array_elements(s.{}{})",
+                    quote_ident(&column.name),
+                    cast
+                ),
+                "jsonb".to_string(),
+            )
+        } else if let Some(element_type) = column.data_type.strip_suffix("[]") {
+            (
+                format!("unnest(s.{})", quote_ident(&column.name)),
+                element_type.to_string(),
+            )
+        } else {
+            return Err(format!("$unwind 的列 {} 不是数组或 json/jsonb", column.name).into());
+        };
+
+        let select: Vec<String> = self
+            .current
+            .columns
+            .iter()
+            .map(|c| {
+                if c.name == column.name {
+                    format!("u.element AS {}", quote_ident(&c.name))
+                } else {
+                    format!("s.{}", quote_ident(&c.name))
+                }
+            })
+            .collect();
+        let join = if preserve {
+            "LEFT JOIN LATERAL"
+        } else {
+            "CROSS JOIN LATERAL"
+        };
+        let on = if preserve { " ON TRUE" } else { "" };
+        let sql = format!(
+            "SELECT {} FROM {} s {} {} AS u(element){}",
+            select.join(", "),
+            self.from(),
+            join,
+            elements,
+            on
+        );
+
+        let name = column.name.clone();
+        let columns = self
+            .current
+            .columns
+            .iter()
+            .map(|c| RelationColumn {
+                name: c.name.clone(),
+                data_type: if c.name == name {
+                    element_type.clone()
+                } else {
+                    c.data_type.clone()
+                },
+            })
+            .collect();
+        self.push(sql, columns);
+        Ok(())
+    }
+
+    /// `{"$lookup": {"from": "orders", "localField": "id", "foreignField": "user_id", "as": "orders"}}`
+    ///
+    /// LEFT JOIN 另一张表，匹配的行聚合为 json 数组放在 `as` 列中，没有匹配时为 `[]`。
+    fn lookup(&mut self, spec: &serde_json::Value, foreign: &Relation) -> Result<(), QueryError> {
+        let option = |key: &str| {
+            spec.get(key)
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| QueryError::from(format!("$lookup 需要 {}", key)))
+        };
+        let local = self.current.column(option("localField")?)?;
+        let foreign_column = foreign.column(option("foreignField")?)?;
+        let alias = option("as")?;
+        if self.current.columns.iter().any(|c| c.name == alias) {
+            return Err(format!("$lookup 的 as 与已有的列重名: {}", alias).into());
+        }
+
+        let sql = format!(
+            "SELECT s.*, coalesce(l.docs, '[]') AS {} FROM {} s LEFT JOIN LATERAL \
+             (SELECT json_agg(f) AS docs FROM {} f WHERE f.{} = s.{}) l ON TRUE",
+            quote_ident(alias),
+            self.from(),
+            foreign.qualified(),
+            quote_ident(&foreign_column.name),
+            quote_ident(&local.name)
+        );
+        let mut columns = self.current.columns.clone();
+        columns.push(RelationColumn {
+            name: alias.to_string(),
+            data_type: "json".to_string(),
+        });
+        self.push(sql, columns);
+        Ok(())
+    }
+}
+
+/// 阶段的输出，列名按此校验；报错时显示为 `表名#阶段`
+fn stage_relation(source: &Relation, stage: usize, columns: Vec<RelationColumn>) -> Relation {
+    Relation {
+        schema: source.schema.clone(),
+        name: if stage == 0 {
+            source.name.clone()
+        } else {
+            format!("{}#{}", source.name, stage)
+        },
+        is_table: false,
+        columns,
+    }
+}
+
+/// 连续的 `$sort`、`$skip`、`$limit`，按这个顺序才能合并为一条 SQL
+#[derive(Default)]
+struct Tail {
+    sort: Option<serde_json::Value>,
+    skip: Option<u64>,
+    limit: Option<u64>,
+}
+
+impl Tail {
+    fn accepts(&self, stage: &str) -> bool {
+        match stage {
+            "$skip" => self.skip.is_none() && self.limit.is_none(),
+            "$limit" => self.limit.is_none(),
+            _ => false,
+        }
+    }
+
+    fn set(&mut self, stage: &str, spec: &serde_json::Value) -> Result<(), QueryError> {
+        match stage {
+            "$sort" => self.sort = Some(spec.clone()),
+            _ => {
+                let value = spec
+                    .as_u64()
+                    .ok_or_else(|| format!("{} 必须是非负整数", stage))?;
+                if stage == "$skip" {
+                    self.skip = Some(value);
+                } else {
+                    self.limit = Some(value);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn relation(name: &str, columns: &[(&str, &str)]) -> Relation {
+        Relation {
+            schema: "public".to_string(),
+            name: name.to_string(),
+            is_table: true,
+            columns: columns
+                .iter()
+                .map(|(name, data_type)| RelationColumn {
+                    name: name.to_string(),
+                    data_type: data_type.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    fn orders() -> Relation {
+        relation(
+            "orders",
+            &[
+                ("id", "integer"),
+                ("city", "text"),
+                ("amount", "numeric"),
+                ("tags", "text[]"),
+                ("payload", "jsonb"),
+            ],
+        )
+    }
+
+    #[test]
+    fn match_then_group() {
+        let source = orders();
+        let mut pipeline = Pipeline::new(&source);
+        pipeline.match_stage(&json!({"city": "Paris"})).unwrap();
+        pipeline
+            .group(&json!({"_id": "$city", "total": {"$sum": "$amount"}}))
+            .unwrap();
+        assert_eq!(
+            pipeline.sql(),
+            "WITH stage_1 AS (SELECT * FROM \"public\".\"orders\" WHERE \"city\" = $1), \
+             stage_2 AS (SELECT \"city\" AS \"_id\", sum(\"amount\") AS \"total\" FROM stage_1 GROUP BY \"city\") \
+             SELECT * FROM stage_2"
+        );
+        let columns: Vec<(&str, &str)> = pipeline
+            .current
+            .columns
+            .iter()
+            .map(|c| (c.name.as_str(), c.data_type.as_str()))
+            .collect();
+        assert_eq!(columns, [("_id", "text"), ("total", "numeric")]);
+
+        // 分组后只能引用分组的输出列
+        assert!(pipeline.match_stage(&json!({"city": "Paris"})).is_err());
+        pipeline
+            .match_stage(&json!({"total": {"$gt": 10}}))
+            .unwrap();
+    }
+
+    #[test]
+    fn empty_match_adds_no_stage() {
+        let source = orders();
+        let mut pipeline = Pipeline::new(&source);
+        pipeline.match_stage(&json!({})).unwrap();
+        assert_eq!(pipeline.sql(), "SELECT * FROM \"public\".\"orders\"");
+        assert!(pipeline.match_stage(&json!({"$and": [{}]})).is_err());
+    }
+
+    #[test]
+    fn group_by_document_id() {
+        let source = orders();
+        let mut pipeline = Pipeline::new(&source);
+        pipeline
+            .group(&json!({"_id": {"city": "$city", "kind": "$payload.kind"}, "n": {"$count": {}}}))
+            .unwrap();
+        assert_eq!(
+            pipeline.ctes,
+            ["stage_1 AS (SELECT jsonb_build_object($1::text, \"city\", $2::text, \"payload\"->$2::text) AS \"_id\", \
+              count(*) AS \"n\" FROM \"public\".\"orders\" GROUP BY \"city\", \"payload\"->$2::text)"]
+        );
+    }
+
+    #[test]
+    fn unwind_array_and_json() {
+        let source = orders();
+        let mut pipeline = Pipeline::new(&source);
+        pipeline.project(&json!({"id": 1, "tags": 1})).unwrap();
+        pipeline.unwind(&json!("$tags")).unwrap();
+        assert_eq!(
+            pipeline.ctes[1],
+            "stage_2 AS (SELECT s.\"id\", u.element AS \"tags\" FROM stage_1 s \
+             CROSS JOIN LATERAL unnest(s.\"tags\") AS u(element))"
+        );
+        assert_eq!(pipeline.current.column("tags").unwrap().data_type, "text");
+
+        let mut pipeline = Pipeline::new(&source);
+        pipeline
+            .unwind(&json!({"path": "$payload", "preserveNullAndEmptyArrays": true}))
+            .unwrap();
+        assert!(pipeline.ctes[0].contains(
+            "LEFT JOIN LATERAL jsonb_array_elements(s.\"payload\") AS u(element) ON TRUE"
+        ));
+        assert!(pipeline.unwind(&json!("$city")).is_err());
+    }
+
+    #[test]
+    fn sort_skip_limit_in_one_stage() {
+        let source = orders();
+        let mut pipeline = Pipeline::new(&source);
+        let mut tail = Tail::default();
+        tail.set("$sort", &json!({"amount": -1})).unwrap();
+        assert!(tail.accepts("$skip"));
+        tail.set("$skip", &json!(20)).unwrap();
+        tail.set("$limit", &json!(10)).unwrap();
+        assert!(!tail.accepts("$skip"));
+        pipeline.tail(&tail).unwrap();
+        assert_eq!(
+            pipeline.ctes,
+            ["stage_1 AS (SELECT * FROM \"public\".\"orders\" ORDER BY \"amount\" DESC LIMIT $1 OFFSET $2)"]
+        );
+        // 外层查询保持排序，之后的阶段不再保证
+        assert!(pipeline
+            .sql()
+            .ends_with("SELECT * FROM stage_1 ORDER BY \"amount\" DESC"));
+        pipeline.match_stage(&json!({"city": "Paris"})).unwrap();
+        assert!(pipeline.sql().ends_with("SELECT * FROM stage_2"));
+    }
+
+    #[test]
+    fn min_max_of_paths_as_numbers() {
+        let source = orders();
+        let mut pipeline = Pipeline::new(&source);
+        pipeline
+            .group(&json!({"_id": null, "low": {"$min": "$payload.price"}}))
+            .unwrap();
+        assert_eq!(
+            pipeline.ctes,
+            ["stage_1 AS (SELECT NULL AS \"_id\", min((\"payload\"->>$1::text)::numeric) AS \"low\" \
+              FROM \"public\".\"orders\")"]
+        );
+        assert_eq!(pipeline.current.column("low").unwrap().data_type, "numeric");
+    }
+
+    #[test]
+    fn lookup_aggregates_matches() {
+        let source = relation("users", &[("id", "integer"), ("name", "text")]);
+        let foreign = orders();
+        let mut pipeline = Pipeline::new(&source);
+        let spec =
+            json!({"from": "orders", "localField": "id", "foreignField": "id", "as": "orders"});
+        pipeline.lookup(&spec, &foreign).unwrap();
+        assert_eq!(
+            pipeline.ctes[0],
+            "stage_1 AS (SELECT s.*, coalesce(l.docs, '[]') AS \"orders\" FROM \"public\".\"users\" s \
+             LEFT JOIN LATERAL (SELECT json_agg(f) AS docs FROM \"public\".\"orders\" f \
+             WHERE f.\"id\" = s.\"id\") l ON TRUE)"
+        );
+        // as 与已有列重名
+        assert!(pipeline.lookup(&spec, &foreign).is_err());
+    }
+}
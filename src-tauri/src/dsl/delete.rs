@@ -0,0 +1,96 @@
+use super::{build_condition, filter_of, query, tidy_sql, Params, QueryResult, Relation};
+use crate::error::QueryError;
+use crate::stmt_cache::StatementCache;
+use tokio_postgres::Client;
+
+/// `{"operation": "deleteOne", "filter": {...}}`，只删除第一条匹配的行
+pub async fn delete_one(
+    client: &Client,
+    cache: &StatementCache,
+    relation: &Relation,
+    request: &serde_json::Value,
+) -> Result<serde_json::Value, QueryError> {
+    if !relation.is_table {
+        return Err(format!("deleteOne 只支持普通表，{} 是视图或外部表", relation.name).into());
+    }
+    delete(client, cache, relation, request, true).await
+}
+
+/// `{"operation": "deleteMany", "filter": {...}}`
+///
+/// filter 为空会删除整张表，必须同时指定 `"confirmAll": true`。
+pub async fn delete_many(
+    client: &Client,
+    cache: &StatementCache,
+    relation: &Relation,
+    request: &serde_json::Value,
+) -> Result<serde_json::Value, QueryError> {
+    delete(client, cache, relation, request, false).await
+}
+
+async fn delete(
+    client: &Client,
+    cache: &StatementCache,
+    relation: &Relation,
+    request: &serde_json::Value,
+    single: bool,
+) -> Result<serde_json::Value, QueryError> {
+    let returning = request
+        .get("returning")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    let mut params = Params::default();
+    let condition = build_condition(relation, &filter_of(request), &mut params)?;
+    let confirm_all = request
+        .get("confirmAll")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    if condition.is_none() && !single && !confirm_all {
+        return Err("filter 为空将删除表中所有行，确认请指定 \"confirmAll\": true".into());
+    }
+
+    let table = relation.qualified();
+    let where_clause = match (single, &condition) {
+        (true, _) => format!(
+            " WHERE (tableoid, ctid) = (SELECT tableoid, ctid FROM {}{} LIMIT 1)",
+            table,
+            condition
+                .as_ref()
+                .map(|c| format!(" WHERE {}", c))
+                .unwrap_or_default()
+        ),
+        (false, Some(condition)) => format!(" WHERE {}", condition),
+        (false, None) => String::new(),
+    };
+    // 返回删除的行时直接 RETURNING *，与查询结果一样按列类型转换；否则只计数
+    let sql = if returning {
+        format!("DELETE FROM {}{} RETURNING *", table, where_clause)
+    } else {
+        format!(
+            "WITH deleted AS (DELETE FROM {}{} RETURNING 1) SELECT count(*) FROM deleted",
+            table, where_clause
+        )
+    };
+
+    let rows = query(client, cache, &sql, &params).await.map_err(|mut e| {
+        e.context = Some("删除失败".to_string());
+        e
+    })?;
+    let (data, deleted) = if returning {
+        let data = crate::rows_to_json(&rows);
+        let deleted = data.len() as u64;
+        (data, deleted)
+    } else {
+        let row = rows.first().ok_or("删除失败: 没有返回结果")?;
+        (Vec::new(), row.get::<_, i64>(0) as u64)
+    };
+
+    QueryResult {
+        data,
+        deleted: Some(deleted),
+        sql: Some(tidy_sql(&sql)),
+        ..Default::default()
+    }
+    .into_value()
+}
@@ -0,0 +1,479 @@
+//! 过滤条件编译
+//!
+//! 递归处理 `$and`、`$or`、`$nor` 以及字段上的运算符，同一字段的多个运算符按 AND 组合。
+//! 未知的运算符直接报错，不会被静默忽略。
+//! 字段名可以是 json/jsonb 列中的路径（`payload.user.id`），按比较值的类型转换后比较。
+
+use super::{Field, Params, Relation};
+use crate::error::QueryError;
+
+type Document = serde_json::Map<String, serde_json::Value>;
+
+/// 比较运算符与 SQL 运算符的对应关系
+const COMPARISONS: &[(&str, &str)] = &[
+    ("$eq", "="),
+    ("$gt", ">"),
+    ("$gte", ">="),
+    ("$lt", "<"),
+    ("$lte", "<="),
+    // 与 MongoDB 一样，$ne 也匹配空值
+    ("$ne", "IS DISTINCT FROM"),
+];
+
+/// 编译过滤条件，返回 ` WHERE ...`，条件为空时返回空字符串
+pub fn build_where_clause(
+    relation: &Relation,
+    filter: &Document,
+    params: &mut Params,
+) -> Result<String, QueryError> {
+    Ok(build_condition(relation, filter, params)?
+        .map(|condition| format!(" WHERE {}", condition))
+        .unwrap_or_default())
+}
+
+/// 编译过滤条件本身（不带 WHERE），条件为空时返回 None
+pub fn build_condition(
+    relation: &Relation,
+    filter: &Document,
+    params: &mut Params,
+) -> Result<Option<String>, QueryError> {
+    let conditions = compile_document(relation, filter, params)?;
+    if conditions.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(conditions.join(" AND ")))
+    }
+}
+
+/// 编译一个过滤文档，返回需要 AND 在一起的条件
+fn compile_document(
+    relation: &Relation,
+    filter: &Document,
+    params: &mut Params,
+) -> Result<Vec<String>, QueryError> {
+    let mut conditions = Vec::with_capacity(filter.len());
+    for (key, value) in filter {
+        let condition = match key.as_str() {
+            "$and" | "$or" | "$nor" => logical(relation, key, value, params)?,
+            _ if key.starts_with('$') => {
+                return Err(format!("不支持的运算符: {}", key).into());
+            }
+            _ => field_condition(relation, key, value, params)?,
+        };
+        conditions.push(condition);
+    }
+    Ok(conditions)
+}
+
+/// `$and` / `$or` / `$nor`：值为过滤文档数组
+fn logical(
+    relation: &Relation,
+    operator: &str,
+    value: &serde_json::Value,
+    params: &mut Params,
+) -> Result<String, QueryError> {
+    let items = value
+        .as_array()
+        .filter(|items| !items.is_empty())
+        .ok_or_else(|| format!("{} 的值必须是非空数组", operator))?;
+
+    let mut parts = Vec::with_capacity(items.len());
+    for item in items {
+        let document = item
+            .as_object()
+            .ok_or_else(|| format!("{} 中的元素必须是对象", operator))?;
+        // 空对象恒为真，会让 deleteMany / updateMany 绕过对空 filter 的确认
+        if document.is_empty() {
+            return Err(format!("{} 中的元素不能是空对象", operator).into());
+        }
+        let mut conditions = compile_document(relation, document, params)?;
+        parts.push(match conditions.len() {
+            1 => conditions.swap_remove(0),
+            _ => format!("({})", conditions.join(" AND ")),
+        });
+    }
+
+    Ok(match operator {
+        "$and" => format!("({})", parts.join(" AND ")),
+        "$or" => format!("({})", parts.join(" OR ")),
+        // 与 MongoDB 一样，结果为 NULL 的行也算“都不满足”
+        _ => format!("(({}) IS NOT TRUE)", parts.join(" OR ")),
+    })
+}
+
+/// 单个字段的条件：普通值按相等比较，运算符对象中的每个运算符都要满足
+fn field_condition(
+    relation: &Relation,
+    key: &str,
+    value: &serde_json::Value,
+    params: &mut Params,
+) -> Result<String, QueryError> {
+    let field = relation.field(key)?;
+
+    match value {
+        serde_json::Value::Object(operators) if operators.keys().any(|k| k.starts_with('$')) => {
+            operator_conditions(&field, key, operators, params)
+        }
+        serde_json::Value::Null => Ok(format!("{} IS NULL", field.expr_for(value, params))),
+        // 普通值（包括不含运算符的对象）按相等比较
+        _ => Ok(format!(
+            "{} = {}",
+            field.expr_for(value, params),
+            params.push(value.clone())
+        )),
+    }
+}
+
+fn operator_conditions(
+    field: &Field,
+    key: &str,
+    operators: &Document,
+    params: &mut Params,
+) -> Result<String, QueryError> {
+    if let Some(plain) = operators.keys().find(|k| !k.starts_with('$')) {
+        return Err(format!("{} 的条件中不能混用运算符和普通字段 {}", key, plain).into());
+    }
+
+    let mut parts = Vec::with_capacity(operators.len());
+    for (operator, operand) in operators {
+        // $options 是 $regex 的附加参数
+        if operator == "$options" {
+            if !operators.contains_key("$regex") {
+                return Err(format!("{} 的 $options 只能与 $regex 一起使用", key).into());
+            }
+            continue;
+        }
+        if operator == "$regex" {
+            parts.push(regex_condition(
+                field,
+                key,
+                operand,
+                operators.get("$options"),
+                params,
+            )?);
+            continue;
+        }
+        parts.push(operator_condition(field, key, operator, operand, params)?);
+    }
+    Ok(if parts.len() == 1 {
+        parts.swap_remove(0)
+    } else {
+        format!("({})", parts.join(" AND "))
+    })
+}
+
+fn operator_condition(
+    field: &Field,
+    key: &str,
+    operator: &str,
+    operand: &serde_json::Value,
+    params: &mut Params,
+) -> Result<String, QueryError> {
+    if let Some((_, sql_operator)) = COMPARISONS.iter().find(|(op, _)| *op == operator) {
+        let target = field.expr_for(operand, params);
+        // 与 null 比较改为 IS [NOT] NULL
+        match (operator, operand) {
+            ("$eq", serde_json::Value::Null) => return Ok(format!("{} IS NULL", target)),
+            ("$ne", serde_json::Value::Null) => return Ok(format!("{} IS NOT NULL", target)),
+            (_, serde_json::Value::Null) => {
+                return Err(format!("{} 的 {} 不能与 null 比较", key, operator).into())
+            }
+            _ => {}
+        }
+        return Ok(format!(
+            "{} {} {}",
+            target,
+            sql_operator,
+            params.push(operand.clone())
+        ));
+    }
+
+    match operator {
+        "$in" | "$nin" => {
+            let items = operand
+                .as_array()
+                .ok_or_else(|| format!("{} 的 {} 必须是数组", key, operator))?;
+            // 整个数组作为一个数组参数，元素类型由列类型推断；null 元素单独处理
+            let has_null = items.iter().any(|v| v.is_null());
+            let values: Vec<serde_json::Value> =
+                items.iter().filter(|v| !v.is_null()).cloned().collect();
+            let target = field.expr_for(values.first().unwrap_or(&serde_json::Value::Null), params);
+            let mut condition = if values.is_empty() {
+                "FALSE".to_string()
+            } else {
+                format!(
+                    "{} = ANY({})",
+                    target,
+                    params.push(serde_json::Value::Array(values))
+                )
+            };
+            if has_null {
+                condition = format!("({} OR {} IS NULL)", condition, target);
+            }
+            Ok(if operator == "$in" {
+                condition
+            } else {
+                format!("({}) IS NOT TRUE", condition)
+            })
+        }
+        "$like" | "$ilike" => {
+            let pattern = operand
+                .as_str()
+                .ok_or_else(|| format!("{} 的 {} 必须是字符串", key, operator))?;
+            Ok(format!(
+                "{} {} {}",
+                field.expr_for(operand, params),
+                if operator == "$like" { "LIKE" } else { "ILIKE" },
+                params.push(pattern.into())
+            ))
+        }
+        "$exists" => {
+            let exists = operand
+                .as_bool()
+                .ok_or_else(|| format!("{} 的 $exists 必须是 true 或 false", key))?;
+            // 路径取 jsonb 值，值为 JSON null 的键也算存在
+            let target = if field.path.is_empty() {
+                field.expr_for(&serde_json::Value::Null, params)
+            } else {
+                field.jsonb_expr(params)
+            };
+            Ok(format!(
+                "{} IS {}NULL",
+                target,
+                if exists { "NOT " } else { "" }
+            ))
+        }
+        "$between" => {
+            let bounds = operand
+                .as_array()
+                .filter(|b| b.len() == 2 && b.iter().all(|v| !v.is_null()))
+                .ok_or_else(|| format!("{} 的 $between 必须是 [下限, 上限]", key))?;
+            Ok(format!(
+                "{} BETWEEN {} AND {}",
+                field.expr_for(&bounds[0], params),
+                params.push(bounds[0].clone()),
+                params.push(bounds[1].clone())
+            ))
+        }
+        "$not" => {
+            let inner = operand
+                .as_object()
+                .filter(|o| !o.is_empty())
+                .ok_or_else(|| format!("{} 的 $not 必须是运算符对象", key))?;
+            let condition = operator_conditions(field, key, inner, params)?;
+            Ok(format!("({}) IS NOT TRUE", condition))
+        }
+        "$contains" => {
+            if field.column.is_json() {
+                Ok(format!(
+                    "{} @> {}::jsonb",
+                    field.jsonb_expr(params),
+                    params.push(operand.clone())
+                ))
+            } else if field.column.data_type.ends_with("[]") && operand.is_array() {
+                Ok(format!(
+                    "{} @> {}",
+                    field.expr_for(operand, params),
+                    params.push(operand.clone())
+                ))
+            } else {
+                Err(format!("{} 的 $contains 只能用于 json/jsonb 或数组列", key).into())
+            }
+        }
+        "$hasKey" => {
+            if !field.column.is_json() {
+                return Err(format!("{} 的 $hasKey 只能用于 json/jsonb 列", key).into());
+            }
+            let name = operand
+                .as_str()
+                .ok_or_else(|| format!("{} 的 $hasKey 必须是字符串", key))?;
+            Ok(format!(
+                "{} ? {}",
+                field.jsonb_expr(params),
+                params.push(name.into())
+            ))
+        }
+        _ => Err(format!("不支持的运算符: {}（字段 {}）", operator, key).into()),
+    }
+}
+
+/// `{"$regex": "^a", "$options": "i"}`，转为 `~` 或不区分大小写的 `~*`
+fn regex_condition(
+    field: &Field,
+    key: &str,
+    pattern: &serde_json::Value,
+    options: Option<&serde_json::Value>,
+    params: &mut Params,
+) -> Result<String, QueryError> {
+    let pattern = pattern
+        .as_str()
+        .ok_or_else(|| format!("{} 的 $regex 必须是字符串", key))?;
+    let options = match options {
+        None => "",
+        Some(value) => value
+            .as_str()
+            .ok_or_else(|| format!("{} 的 $options 必须是字符串", key))?,
+    };
+    if let Some(option) = options.chars().find(|c| *c != 'i') {
+        return Err(format!("不支持的正则选项: {}（只支持 i）", option).into());
+    }
+
+    Ok(format!(
+        "{} {} {}",
+        field.expr_for(&pattern.into(), params),
+        if options.contains('i') { "~*" } else { "~" },
+        params.push(pattern.into())
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsl::RelationColumn;
+    use serde_json::json;
+
+    fn relation() -> Relation {
+        let column = |name: &str, data_type: &str| RelationColumn {
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+        };
+        Relation {
+            schema: "public".to_string(),
+            name: "users".to_string(),
+            is_table: true,
+            columns: vec![
+                column("id", "integer"),
+                column("name", "text"),
+                column("tags", "text[]"),
+                column("payload", "jsonb"),
+                column("meta", "json"),
+            ],
+        }
+    }
+
+    /// 编译过滤条件，返回条件和参数值
+    fn compile(filter: serde_json::Value) -> Result<(String, Vec<serde_json::Value>), QueryError> {
+        let mut params = Params::default();
+        let condition = build_condition(&relation(), filter.as_object().unwrap(), &mut params)?;
+        let values = params.values.into_iter().map(|p| p.0).collect();
+        Ok((condition.unwrap_or_default(), values))
+    }
+
+    #[test]
+    fn equality_and_comparisons() {
+        assert_eq!(
+            compile(json!({"id": {"$gte": 1, "$lt": 10}})).unwrap(),
+            (
+                "(\"id\" >= $1 AND \"id\" < $2)".to_string(),
+                vec![json!(1), json!(10)]
+            )
+        );
+        assert_eq!(
+            compile(json!({"name": null})).unwrap().0,
+            "\"name\" IS NULL"
+        );
+        assert_eq!(
+            compile(json!({"name": {"$ne": "a"}})).unwrap().0,
+            "\"name\" IS DISTINCT FROM $1"
+        );
+    }
+
+    #[test]
+    fn logical_operators() {
+        assert_eq!(
+            compile(json!({"$or": [{"id": 1}, {"name": "a"}]}))
+                .unwrap()
+                .0,
+            "(\"id\" = $1 OR \"name\" = $2)"
+        );
+        assert_eq!(
+            compile(json!({"$nor": [{"id": 1}]})).unwrap().0,
+            "((\"id\" = $1) IS NOT TRUE)"
+        );
+    }
+
+    #[test]
+    fn rejects_empty_logical_documents() {
+        // 空对象恒为真，不能用来绕过 deleteMany 的 confirmAll
+        assert!(compile(json!({"$and": []})).is_err());
+        assert!(compile(json!({"$and": [{}]})).is_err());
+        assert!(compile(json!({"$or": [{"id": 1}, {}]})).is_err());
+        assert!(compile(json!({"$nor": []})).is_err());
+        assert!(compile(json!({"$and": [{"$or": [{}]}]})).is_err());
+        assert_eq!(compile(json!({})).unwrap().0, "");
+    }
+
+    #[test]
+    fn in_with_null() {
+        assert_eq!(
+            compile(json!({"id": {"$in": [1, null, 2]}})).unwrap(),
+            (
+                "(\"id\" = ANY($1) OR \"id\" IS NULL)".to_string(),
+                vec![json!([1, 2])]
+            )
+        );
+    }
+
+    #[test]
+    fn regex_and_unknown_operators() {
+        assert_eq!(
+            compile(json!({"name": {"$regex": "^a", "$options": "i"}}))
+                .unwrap()
+                .0,
+            "\"name\" ~* $1"
+        );
+        assert!(compile(json!({"name": {"$options": "i"}})).is_err());
+        assert!(compile(json!({"name": {"$foo": 1}})).is_err());
+        assert!(compile(json!({"$where": "1"})).is_err());
+    }
+
+    #[test]
+    fn json_paths() {
+        // 键名作为参数绑定，数组下标直接写入
+        assert_eq!(
+            compile(json!({"payload.user.id": 42})).unwrap(),
+            (
+                "(\"payload\"->$1::text->>$2::text)::numeric = $3".to_string(),
+                vec![json!("user"), json!("id"), json!(42)]
+            )
+        );
+        assert_eq!(
+            compile(json!({"meta.tags.0": "a"})).unwrap().0,
+            "\"meta\"::jsonb->$1::text->>0 = $2"
+        );
+        assert_eq!(
+            compile(json!({"payload.it's": "a"})).unwrap().1,
+            vec![json!("it's"), json!("a")]
+        );
+        assert!(compile(json!({"name.first": "a"})).is_err());
+    }
+
+    #[test]
+    fn json_column_compares_as_jsonb() {
+        assert_eq!(
+            compile(json!({"meta": {"a": 1}})).unwrap().0,
+            "\"meta\"::jsonb = $1"
+        );
+        assert_eq!(
+            compile(json!({"payload": {"a": 1}})).unwrap().0,
+            "\"payload\" = $1"
+        );
+    }
+
+    #[test]
+    fn contains_binds_jsonb_strings_as_json() {
+        // 字符串保持为 JSON 值，绑定时按 jsonb 文本 `"vip"` 发送
+        assert_eq!(
+            compile(json!({"payload.tags": {"$contains": "vip"}})).unwrap(),
+            (
+                "\"payload\"->$1::text @> $2::jsonb".to_string(),
+                vec![json!("tags"), json!("vip")]
+            )
+        );
+        assert_eq!(
+            compile(json!({"tags": {"$contains": ["a"]}})).unwrap().0,
+            "\"tags\" @> $1"
+        );
+        assert!(compile(json!({"name": {"$contains": "a"}})).is_err());
+    }
+}
@@ -0,0 +1,266 @@
+use super::{build_where_clause, filter_of, query, tidy_sql, Params, QueryResult, Relation};
+use crate::error::QueryError;
+use crate::ident::quote_ident;
+use crate::stmt_cache::StatementCache;
+use tokio_postgres::Client;
+
+/// `{"operation": "find", "filter": {...}, "projection": {...}, "sort": {...}, "skip": 0, "limit": 20, "withTotal": true}`
+pub async fn find(
+    client: &Client,
+    cache: &StatementCache,
+    relation: &Relation,
+    request: &serde_json::Value,
+) -> Result<serde_json::Value, QueryError> {
+    let mut params = Params::default();
+    let where_clause = build_where_clause(relation, &filter_of(request), &mut params)?;
+    let mut sql = format!(
+        "SELECT {} FROM {}{}{}",
+        select_list(relation, request.get("projection"), &mut params)?,
+        relation.qualified(),
+        where_clause,
+        order_by(relation, request.get("sort"), &mut params)?
+    );
+    if let Some(limit) = non_negative(request, "limit")? {
+        sql.push_str(&format!(" LIMIT {}", params.push(limit.into())));
+    }
+    if let Some(skip) = non_negative(request, "skip")? {
+        sql.push_str(&format!(" OFFSET {}", params.push(skip.into())));
+    }
+
+    let rows = query(client, cache, &sql, &params).await?;
+
+    // 分页前的总行数，供分页表格显示
+    let with_total = request
+        .get("withTotal")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let total = if with_total {
+        Some(count_rows(client, cache, relation, request).await?.0)
+    } else {
+        None
+    };
+
+    QueryResult {
+        data: crate::rows_to_json(&rows),
+        total,
+        sql: Some(tidy_sql(&sql)),
+        ..Default::default()
+    }
+    .into_value()
+}
+
+/// 与 find 相同的选项，只返回第一行
+pub async fn find_one(
+    client: &Client,
+    cache: &StatementCache,
+    relation: &Relation,
+    request: &serde_json::Value,
+) -> Result<serde_json::Value, QueryError> {
+    let mut params = Params::default();
+    let where_clause = build_where_clause(relation, &filter_of(request), &mut params)?;
+    let mut sql = format!(
+        "SELECT {} FROM {}{}{} LIMIT 1",
+        select_list(relation, request.get("projection"), &mut params)?,
+        relation.qualified(),
+        where_clause,
+        order_by(relation, request.get("sort"), &mut params)?
+    );
+    if let Some(skip) = non_negative(request, "skip")? {
+        sql.push_str(&format!(" OFFSET {}", params.push(skip.into())));
+    }
+
+    let rows = query(client, cache, &sql, &params).await?;
+    QueryResult {
+        data: rows.first().map(crate::row_to_json).into_iter().collect(),
+        sql: Some(tidy_sql(&sql)),
+        ..Default::default()
+    }
+    .into_value()
+}
+
+pub async fn count(
+    client: &Client,
+    cache: &StatementCache,
+    relation: &Relation,
+    request: &serde_json::Value,
+) -> Result<serde_json::Value, QueryError> {
+    let (count, sql) = count_rows(client, cache, relation, request).await?;
+    QueryResult {
+        total: Some(count),
+        sql: Some(tidy_sql(&sql)),
+        ..Default::default()
+    }
+    .into_value()
+}
+
+/// `{"operation": "distinct", "field": "city", "filter": {...}, "limit": 50}`
+///
+/// 返回不同的取值及各自的行数，按行数从多到少排列，字段可以是 json/jsonb 路径。
+pub async fn distinct(
+    client: &Client,
+    cache: &StatementCache,
+    relation: &Relation,
+    request: &serde_json::Value,
+) -> Result<serde_json::Value, QueryError> {
+    let key = request
+        .get("field")
+        .and_then(|v| v.as_str())
+        .ok_or("distinct 需要 field")?;
+    let field = relation.field(key)?;
+    let mut params = Params::default();
+    let value = if field.path.is_empty() {
+        quote_ident(&field.column.name)
+    } else {
+        field.jsonb_expr(&mut params)
+    };
+    let where_clause = build_where_clause(relation, &filter_of(request), &mut params)?;
+    let mut sql = format!(
+        "SELECT {} AS value, COUNT(*) AS count FROM {}{} GROUP BY 1 ORDER BY count DESC, value",
+        value,
+        relation.qualified(),
+        where_clause
+    );
+    if let Some(limit) = non_negative(request, "limit")? {
+        sql.push_str(&format!(" LIMIT {}", params.push(limit.into())));
+    }
+
+    let rows = query(client, cache, &sql, &params).await?;
+    QueryResult {
+        data: crate::rows_to_json(&rows),
+        sql: Some(tidy_sql(&sql)),
+        ..Default::default()
+    }
+    .into_value()
+}
+
+/// 符合 filter 的行数，同时返回执行的 SQL
+async fn count_rows(
+    client: &Client,
+    cache: &StatementCache,
+    relation: &Relation,
+    request: &serde_json::Value,
+) -> Result<(i64, String), QueryError> {
+    let mut params = Params::default();
+    let where_clause = build_where_clause(relation, &filter_of(request), &mut params)?;
+    let sql = format!(
+        "SELECT COUNT(*) AS count FROM {}{}",
+        relation.qualified(),
+        where_clause
+    );
+
+    let rows = query(client, cache, &sql, &params).await.map_err(|mut e| {
+        e.context = Some("计数失败".to_string());
+        e
+    })?;
+    let count: i64 = rows.first().map(|row| row.get("count")).unwrap_or(0);
+    Ok((count, sql))
+}
+
+/// projection 为 `{"name": 1, "email": 1}`（只取这些列）或 `{"password": 0}`（排除这些列），不能混用
+///
+/// 包含时可以使用 json/jsonb 路径，如 `{"payload.user.id": 1}`，结果列名就是路径本身。
+pub(super) fn select_list(
+    relation: &Relation,
+    projection: Option<&serde_json::Value>,
+    params: &mut Params,
+) -> Result<String, QueryError> {
+    let projection = match projection {
+        None | Some(serde_json::Value::Null) => return Ok("*".to_string()),
+        Some(value) => value.as_object().ok_or("projection 必须是对象")?,
+    };
+    if projection.is_empty() {
+        return Ok("*".to_string());
+    }
+
+    let mut include = Vec::new();
+    let mut exclude = Vec::new();
+    for (key, flag) in projection {
+        let field = relation.field(key)?;
+        match flag_value(flag) {
+            Some(true) if field.path.is_empty() => include.push(quote_ident(&field.column.name)),
+            Some(true) => include.push(format!(
+                "{} AS {}",
+                field.jsonb_expr(params),
+                quote_ident(key)
+            )),
+            Some(false) if field.path.is_empty() => exclude.push(field.column.name.as_str()),
+            Some(false) => return Err(format!("projection 不能排除路径 {}", key).into()),
+            None => {
+                return Err(format!("projection 中 {} 的值必须是 1/0 或 true/false", key).into())
+            }
+        }
+    }
+
+    match (include.is_empty(), exclude.is_empty()) {
+        (false, false) => Err("projection 不能同时包含和排除列".into()),
+        (false, true) => Ok(include.join(", ")),
+        _ => {
+            let remaining: Vec<String> = relation
+                .columns
+                .iter()
+                .filter(|c| !exclude.contains(&c.name.as_str()))
+                .map(|c| quote_ident(&c.name))
+                .collect();
+            if remaining.is_empty() {
+                return Err("projection 排除了所有列".into());
+            }
+            Ok(remaining.join(", "))
+        }
+    }
+}
+
+pub(super) fn flag_value(value: &serde_json::Value) -> Option<bool> {
+    match value {
+        serde_json::Value::Bool(b) => Some(*b),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(1) => Some(true),
+            Some(0) => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// sort 为 `{"created_at": -1, "name": 1}`，按键的顺序排序；也接受 "asc" / "desc"
+///
+/// json/jsonb 路径按 jsonb 的顺序比较，数字之间按数值排序。
+pub(super) fn order_by(
+    relation: &Relation,
+    sort: Option<&serde_json::Value>,
+    params: &mut Params,
+) -> Result<String, QueryError> {
+    let sort = match sort {
+        None | Some(serde_json::Value::Null) => return Ok(String::new()),
+        Some(value) => value.as_object().ok_or("sort 必须是对象")?,
+    };
+
+    let mut items = Vec::with_capacity(sort.len());
+    for (key, direction) in sort {
+        let field = relation.field(key)?;
+        let direction = match direction {
+            serde_json::Value::Number(n) if n.as_i64() == Some(1) => "ASC",
+            serde_json::Value::Number(n) if n.as_i64() == Some(-1) => "DESC",
+            serde_json::Value::String(s) if s.eq_ignore_ascii_case("asc") => "ASC",
+            serde_json::Value::String(s) if s.eq_ignore_ascii_case("desc") => "DESC",
+            _ => return Err(format!("sort 中 {} 的方向必须是 1 或 -1", key).into()),
+        };
+        items.push(format!("{} {}", field.jsonb_expr(params), direction));
+    }
+
+    if items.is_empty() {
+        Ok(String::new())
+    } else {
+        Ok(format!(" ORDER BY {}", items.join(", ")))
+    }
+}
+
+/// 读取非负整数选项（skip、limit）
+fn non_negative(request: &serde_json::Value, key: &str) -> Result<Option<u64>, QueryError> {
+    match request.get(key) {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .map(Some)
+            .ok_or_else(|| format!("{} 必须是非负整数", key).into()),
+    }
+}
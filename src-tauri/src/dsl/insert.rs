@@ -0,0 +1,187 @@
+use super::upsert::{self, Upsert};
+use super::{query, tidy_sql, Params, QueryResult, Relation, RelationColumn};
+use crate::error::QueryError;
+use crate::ident::quote_ident;
+use crate::stmt_cache::StatementCache;
+use tokio_postgres::Client;
+
+type Document = serde_json::Map<String, serde_json::Value>;
+
+/// `{"operation": "insertOne", "document": {...}, "upsert": true}`
+pub async fn insert_one(
+    client: &Client,
+    cache: &StatementCache,
+    relation: &Relation,
+    request: &serde_json::Value,
+) -> Result<serde_json::Value, QueryError> {
+    let document = request
+        .get("document")
+        .and_then(|v| v.as_object())
+        .ok_or("insertOne 需要 document 对象")?;
+    insert(
+        client,
+        cache,
+        relation,
+        std::slice::from_ref(document),
+        request,
+    )
+    .await
+}
+
+/// `{"operation": "insertMany", "documents": [{...}, ...]}`
+pub async fn insert_many(
+    client: &Client,
+    cache: &StatementCache,
+    relation: &Relation,
+    request: &serde_json::Value,
+) -> Result<serde_json::Value, QueryError> {
+    let documents = request
+        .get("documents")
+        .and_then(|v| v.as_array())
+        .ok_or("insertMany 需要 documents 数组")?
+        .iter()
+        .map(|v| v.as_object().cloned().ok_or("documents 中的元素必须是对象"))
+        .collect::<Result<Vec<Document>, _>>()?;
+    if documents.is_empty() {
+        return Err("documents 不能为空".into());
+    }
+    insert(client, cache, relation, &documents, request).await
+}
+
+async fn insert(
+    client: &Client,
+    cache: &StatementCache,
+    relation: &Relation,
+    documents: &[Document],
+    request: &serde_json::Value,
+) -> Result<serde_json::Value, QueryError> {
+    let upsert = upsert::upsert_of(relation, request)?;
+
+    let mut params = Params::default();
+    let columns = insert_columns(relation, documents)?;
+    let mut sql = build_insert(relation, &columns, documents, &mut params)?;
+    if let Some(upsert) = &upsert {
+        sql.push_str(&conflict_clause(client, cache, relation, upsert, &columns).await?);
+        sql.push_str(upsert::RETURNING);
+    } else {
+        sql.push_str(" RETURNING *");
+    }
+
+    let rows = query(client, cache, &sql, &params).await.map_err(|mut e| {
+        e.context = Some("插入失败".to_string());
+        e
+    })?;
+    let data = crate::rows_to_json(&rows);
+    let (inserted, updated, skipped) = match upsert {
+        Some(_) => {
+            let (inserted, updated) = upsert::count_rows(&data);
+            // DO NOTHING 跳过的行不出现在 RETURNING 中
+            let skipped = (documents.len() - data.len()) as u64;
+            (inserted, Some(updated), Some(skipped))
+        }
+        None => (data.len() as u64, None, None),
+    };
+    QueryResult {
+        inserted: Some(inserted),
+        updated,
+        skipped,
+        data,
+        sql: Some(tidy_sql(&sql)),
+        ..Default::default()
+    }
+    .into_value()
+}
+
+/// 冲突时默认用新值（EXCLUDED）更新除冲突列以外插入的所有列
+///
+/// 指定的 `update` 列必须是插入的列，否则 EXCLUDED 中只有默认值。
+async fn conflict_clause(
+    client: &Client,
+    cache: &StatementCache,
+    relation: &Relation,
+    upsert: &Upsert,
+    columns: &[&RelationColumn],
+) -> Result<String, QueryError> {
+    let available: Vec<&str> = columns.iter().map(|c| c.name.as_str()).collect();
+    let conflict = upsert::conflict_columns(client, cache, relation, upsert, &available).await?;
+
+    let updated: Vec<&str> = match upsert.update() {
+        Some(update) => {
+            if let Some(missing) = update.iter().find(|c| !available.contains(&c.as_str())) {
+                return Err(upsert::no_new_value(missing));
+            }
+            update.iter().map(String::as_str).collect()
+        }
+        None => available
+            .iter()
+            .copied()
+            .filter(|c| !conflict.iter().any(|k| k == c))
+            .collect(),
+    };
+    let assignments: Vec<(String, String)> = updated
+        .iter()
+        .map(|c| (quote_ident(c), format!("EXCLUDED.{}", quote_ident(c))))
+        .collect();
+    Ok(upsert::on_conflict(&conflict, &assignments))
+}
+
+/// 所有文档键的并集，按首次出现的顺序
+fn insert_columns<'r>(
+    relation: &'r Relation,
+    documents: &[Document],
+) -> Result<Vec<&'r RelationColumn>, QueryError> {
+    let mut columns: Vec<&RelationColumn> = Vec::new();
+    for document in documents {
+        for key in document.keys() {
+            let column = relation.column(key)?;
+            if !columns.iter().any(|c| c.name == column.name) {
+                columns.push(column);
+            }
+        }
+    }
+    Ok(columns)
+}
+
+/// 生成 `INSERT ... VALUES ...`（不含 RETURNING）
+///
+/// 文档中缺少的列写 DEFAULT；值按列类型转换。
+fn build_insert(
+    relation: &Relation,
+    columns: &[&RelationColumn],
+    documents: &[Document],
+    params: &mut Params,
+) -> Result<String, QueryError> {
+    // 所有文档都为空时，用第一列的 DEFAULT 表示整行取默认值
+    if columns.is_empty() {
+        let first = relation.columns.first().ok_or("表没有任何列")?;
+        let rows = vec!["(DEFAULT)"; documents.len()].join(", ");
+        return Ok(format!(
+            "INSERT INTO {} ({}) VALUES {}",
+            relation.qualified(),
+            quote_ident(&first.name),
+            rows
+        ));
+    }
+
+    let rows: Vec<String> = documents
+        .iter()
+        .map(|document| {
+            let values: Vec<String> = columns
+                .iter()
+                .map(|column| match document.get(&column.name) {
+                    Some(value) => params.push_typed(value.clone(), column),
+                    None => "DEFAULT".to_string(),
+                })
+                .collect();
+            format!("({})", values.join(", "))
+        })
+        .collect();
+
+    let column_list: Vec<String> = columns.iter().map(|c| quote_ident(&c.name)).collect();
+    Ok(format!(
+        "INSERT INTO {} ({}) VALUES {}",
+        relation.qualified(),
+        column_list.join(", "),
+        rows.join(", ")
+    ))
+}
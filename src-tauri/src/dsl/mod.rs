@@ -0,0 +1,387 @@
+//! JSON 查询（MongoDB 风格）编译为 SQL
+//!
+//! 表名和列名一律按标识符引用，过滤条件中的值以 `$n` 参数绑定，
+//! 不会因为值中的引号出错，也无法通过键名注入 SQL。
+
+mod aggregate;
+mod delete;
+mod filter;
+mod find;
+mod insert;
+mod update;
+mod upsert;
+
+pub use aggregate::aggregate;
+pub use delete::{delete_many, delete_one};
+pub use filter::{build_condition, build_where_clause};
+pub use find::{count, distinct, find, find_one};
+pub use insert::{insert_many, insert_one};
+pub use update::{update_many, update_one};
+
+use crate::error::QueryError;
+use crate::formatter;
+use crate::ident::quote_ident;
+use crate::params::{self, JsonParam};
+use crate::stmt_cache::StatementCache;
+use serde::Serialize;
+use tokio_postgres::{Client, Row};
+
+#[derive(Default, Serialize)]
+pub struct QueryResult {
+    pub data: Vec<serde_json::Value>,
+    pub total: Option<i64>,
+    /// 写操作插入的行数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inserted: Option<u64>,
+    /// 更新时符合条件的行数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matched: Option<u64>,
+    /// 更新时值确实发生变化的行数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<u64>,
+    /// upsert 时因冲突而更新的行数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated: Option<u64>,
+    /// upsert 时因冲突而跳过（DO NOTHING）的行数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<u64>,
+    /// 删除的行数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted: Option<u64>,
+    /// 由 JSON 查询生成并格式化后的 SQL
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sql: Option<String>,
+}
+
+impl QueryResult {
+    pub fn into_value(self) -> Result<serde_json::Value, QueryError> {
+        serde_json::to_value(&self).map_err(|e| format!("序列化失败: {}", e).into())
+    }
+}
+
+/// 生成 SQL 时收集的参数，`push` 返回对应的 `$n` 占位符
+#[derive(Default)]
+pub struct Params {
+    values: Vec<JsonParam>,
+    /// 已绑定的路径键名及其占位符，同一个键只绑定一次，
+    /// 使 SELECT 与 GROUP BY 中的相同路径生成相同的表达式
+    keys: Vec<(String, String)>,
+}
+
+impl Params {
+    pub fn push(&mut self, value: serde_json::Value) -> String {
+        self.values.push(JsonParam(value));
+        format!("${}", self.values.len())
+    }
+
+    /// 带类型转换的占位符，如 `$1::integer`，值按列类型解析
+    pub fn push_typed(&mut self, value: serde_json::Value, column: &RelationColumn) -> String {
+        format!("{}::{}", self.push(value), column.data_type)
+    }
+
+    /// json/jsonb 路径中的键名，如 `$1::text`
+    pub fn push_key(&mut self, key: &str) -> String {
+        if let Some((_, placeholder)) = self.keys.iter().find(|(k, _)| k == key) {
+            return placeholder.clone();
+        }
+        let placeholder = format!("{}::text", self.push(key.into()));
+        self.keys.push((key.to_string(), placeholder.clone()));
+        placeholder
+    }
+}
+
+#[derive(Clone)]
+pub struct RelationColumn {
+    pub name: String,
+    /// 不带长度和精度的类型名，如 `integer`、`character varying`、`bpchar`、`text[]`，
+    /// 用于参数的类型转换，带长度的 `::varchar(20)` 会静默截断超长的值
+    pub data_type: String,
+}
+
+/// 经目录校验过的表或视图
+pub struct Relation {
+    pub schema: String,
+    pub name: String,
+    /// 普通表或分区表（有 ctid），视图和外部表为 false
+    pub is_table: bool,
+    pub columns: Vec<RelationColumn>,
+}
+
+impl Relation {
+    /// 引用后的完整名称，如 `"public"."users"`
+    pub fn qualified(&self) -> String {
+        format!("{}.{}", quote_ident(&self.schema), quote_ident(&self.name))
+    }
+
+    /// 按名称查找列，不存在时报错
+    pub fn column(&self, name: &str) -> Result<&RelationColumn, QueryError> {
+        self.columns
+            .iter()
+            .find(|c| c.name == name)
+            .ok_or_else(|| format!("列不存在: {}（表 {}.{}）", name, self.schema, self.name).into())
+    }
+
+    /// 解析过滤、投影和排序中的字段名
+    ///
+    /// 与列名完全相同时直接使用该列；否则按 `.` 拆分，第一段必须是 json/jsonb 列，
+    /// 其余部分是文档内的路径，如 `payload.user.id`。
+    pub fn field(&self, key: &str) -> Result<Field<'_>, QueryError> {
+        if let Some(column) = self.columns.iter().find(|c| c.name == key) {
+            return Ok(Field {
+                column,
+                path: Vec::new(),
+            });
+        }
+        let Some((name, rest)) = key.split_once('.') else {
+            // 不是路径，按普通列报错
+            return self.column(key).map(|column| Field {
+                column,
+                path: Vec::new(),
+            });
+        };
+        let column = self.column(name)?;
+        if !column.is_json() {
+            return Err(format!(
+                "列 {} 的类型是 {}，不能使用路径 {}",
+                name, column.data_type, key
+            )
+            .into());
+        }
+        if rest.split('.').any(str::is_empty) {
+            return Err(format!("路径格式错误: {}", key).into());
+        }
+        Ok(Field {
+            column,
+            path: rest.split('.').map(str::to_string).collect(),
+        })
+    }
+}
+
+impl RelationColumn {
+    pub fn is_json(&self) -> bool {
+        self.data_type == "json" || self.data_type == "jsonb"
+    }
+}
+
+/// 列或 json/jsonb 列中的路径
+pub struct Field<'a> {
+    pub column: &'a RelationColumn,
+    /// 文档内的路径，为空表示整列
+    pub path: Vec<String>,
+}
+
+impl Field<'_> {
+    /// 取值为 jsonb 的表达式，如 `"payload"->$1::text->$2::text`，json 列先转换为 jsonb，
+    /// 路径中的键名作为参数绑定
+    pub fn jsonb_expr(&self, params: &mut Params) -> String {
+        self.path_expr(&self.path, params)
+    }
+
+    fn path_expr(&self, path: &[String], params: &mut Params) -> String {
+        let mut expr = quote_ident(&self.column.name);
+        if self.column.data_type == "json" {
+            expr = format!("{}::jsonb", expr);
+        }
+        for segment in path {
+            expr = format!("{}->{}", expr, path_segment(segment, params));
+        }
+        expr
+    }
+
+    /// 与给定值比较时使用的表达式
+    ///
+    /// 整列直接使用列名，json 列没有 `=` 运算符，转换为 jsonb；路径取文本（`->>`），
+    /// 再按值的类型转换为 numeric 或 boolean，对象和数组则按 jsonb 比较。
+    pub fn expr_for(&self, value: &serde_json::Value, params: &mut Params) -> String {
+        let Some((last, parents)) = self.path.split_last() else {
+            return self.jsonb_expr(params);
+        };
+        let parents = self.path_expr(parents, params);
+        let last = path_segment(last, params);
+        let text = format!("{}->>{}", parents, last);
+        match value {
+            serde_json::Value::Number(_) => format!("({})::numeric", text),
+            serde_json::Value::Bool(_) => format!("({})::boolean", text),
+            serde_json::Value::Object(_) | serde_json::Value::Array(_) => {
+                format!("{}->{}", parents, last)
+            }
+            _ => text,
+        }
+    }
+}
+
+/// 路径中的一段：纯数字按数组下标处理，其它按键名绑定为参数
+fn path_segment(segment: &str, params: &mut Params) -> String {
+    if !segment.is_empty() && segment.len() < 10 && segment.bytes().all(|b| b.is_ascii_digit()) {
+        segment.to_string()
+    } else {
+        params.push_key(segment)
+    }
+}
+
+/// 在目录中查找表、视图或物化视图
+///
+/// 没有指定 schema 时在 search_path 上查找，`table` 也可以写成 `schema.table`。
+/// 与未加引号的 SQL 一样，找不到原名时按小写再找一次。
+pub async fn resolve_relation(
+    client: &Client,
+    cache: &StatementCache,
+    schema: Option<&str>,
+    table: &str,
+) -> Result<Relation, QueryError> {
+    // 名称本身可能带点号，先按原样查找
+    let mut candidates = vec![(schema, table)];
+    if schema.is_none() {
+        if let Some((schema, table)) = table.split_once('.') {
+            candidates.push((Some(schema), table));
+        }
+    }
+
+    let mut found = None;
+    for (schema, table) in candidates {
+        let rows = cache
+            .query(
+                client,
+                "SELECT c.oid, n.nspname, c.relname, c.relkind IN ('r', 'p')
+                 FROM pg_class c JOIN pg_namespace n ON n.oid = c.relnamespace
+                 WHERE c.relname IN ($1, lower($1::text))
+                   AND c.relkind IN ('r', 'p', 'v', 'm', 'f')
+                   AND CASE WHEN $2::text IS NULL THEN pg_table_is_visible(c.oid)
+                            ELSE n.nspname IN ($2, lower($2::text)) END
+                 ORDER BY c.relname = $1 DESC, n.nspname = $2 DESC
+                 LIMIT 1",
+                &[&table, &schema],
+            )
+            .await
+            .map_err(|e| QueryError::from_pg("查询表信息失败", &e))?;
+        if let Some(row) = rows.into_iter().next() {
+            found = Some(row);
+            break;
+        }
+    }
+    let Some(row) = found else {
+        let requested = match schema {
+            Some(schema) => format!("{}.{}", schema, table),
+            None => table.to_string(),
+        };
+        return Err(not_found(client, &requested).await);
+    };
+    let oid: u32 = row.get(0);
+
+    let columns = cache
+        .query(
+            client,
+            // typmod 传 -1 而不是 NULL：NULL 时 char(n) 显示为 character，即 char(1)
+            "SELECT attname, format_type(atttypid, -1)
+             FROM pg_attribute
+             WHERE attrelid = $1 AND attnum > 0 AND NOT attisdropped
+             ORDER BY attnum",
+            &[&oid],
+        )
+        .await
+        .map_err(|e| QueryError::from_pg("查询列信息失败", &e))?
+        .iter()
+        .map(|row| RelationColumn {
+            name: row.get(0),
+            data_type: row.get(1),
+        })
+        .collect();
+
+    Ok(Relation {
+        schema: row.get(1),
+        name: row.get(2),
+        is_table: row.get(3),
+        columns,
+    })
+}
+
+/// 所有用户表、视图和物化视图的名称
+///
+/// search_path 上可见的只写表名，其它写成 `schema.table`，两种写法都能直接用于 `table`。
+pub async fn relation_names(client: &Client) -> Result<Vec<String>, QueryError> {
+    let rows = client
+        .query(
+            "SELECT CASE WHEN pg_table_is_visible(c.oid) THEN c.relname
+                         ELSE n.nspname || '.' || c.relname END
+             FROM pg_class c JOIN pg_namespace n ON n.oid = c.relnamespace
+             WHERE c.relkind IN ('r', 'p', 'v', 'm', 'f')
+               AND n.nspname NOT LIKE 'pg\\_%'
+               AND n.nspname <> 'information_schema'
+             ORDER BY pg_table_is_visible(c.oid) DESC, n.nspname, c.relname",
+            &[],
+        )
+        .await
+        .map_err(|e| QueryError::from_pg("获取表列表失败", &e))?;
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+/// 表不存在的错误，附上名称相近的表
+async fn not_found(client: &Client, requested: &str) -> QueryError {
+    let names = relation_names(client).await.unwrap_or_default();
+    let target = requested.to_lowercase();
+    let limit = (target.chars().count() / 3).max(2);
+
+    let mut matches: Vec<(usize, &String)> = names
+        .iter()
+        .filter_map(|name| {
+            let candidate = name.to_lowercase();
+            // 不写 schema 时只比较表名部分
+            let bare = candidate.rsplit('.').next().unwrap_or(&candidate);
+            let distance = edit_distance(&target, &candidate).min(edit_distance(&target, bare));
+            let related = target.len() >= 3 && candidate.contains(&target);
+            (distance <= limit || related).then_some((distance, name))
+        })
+        .collect();
+    matches.sort();
+
+    let mut message = format!("表不存在: {}", requested);
+    if !matches.is_empty() {
+        let names: Vec<&str> = matches.iter().take(5).map(|(_, n)| n.as_str()).collect();
+        message.push_str(&format!("，相近的有: {}", names.join(", ")));
+    }
+    QueryError::new(message)
+}
+
+/// 编辑距离（按字符）
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            current[j + 1] = (previous[j] + cost)
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// 请求中的 filter，缺省为空
+pub fn filter_of(request: &serde_json::Value) -> serde_json::Map<String, serde_json::Value> {
+    request
+        .get("filter")
+        .and_then(|v| v.as_object())
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// 经语句缓存执行生成的查询
+pub async fn query(
+    client: &Client,
+    cache: &StatementCache,
+    sql: &str,
+    params: &Params,
+) -> Result<Vec<Row>, QueryError> {
+    cache
+        .query(client, sql, &params::as_refs(&params.values))
+        .await
+        .map_err(|e| QueryError::from_pg("查询失败", &e).locate(sql, 0))
+}
+
+/// 生成的 SQL 按默认选项格式化后随结果返回
+pub fn tidy_sql(sql: &str) -> String {
+    formatter::format_sql(sql, &formatter::FormatOptions::default())
+}
@@ -0,0 +1,347 @@
+use super::upsert::{self, Upsert};
+use super::{build_condition, filter_of, query, tidy_sql, Params, QueryResult, Relation};
+use crate::error::QueryError;
+use crate::ident::quote_ident;
+use crate::stmt_cache::StatementCache;
+use tokio_postgres::Client;
+
+/// `{"operation": "updateOne", "filter": {...}, "update": {"$set": {...}}}`
+///
+/// 只更新第一条匹配的行，通过 (tableoid, ctid) 定位，因此只支持普通表。
+pub async fn update_one(
+    client: &Client,
+    cache: &StatementCache,
+    relation: &Relation,
+    request: &serde_json::Value,
+) -> Result<serde_json::Value, QueryError> {
+    if !relation.is_table {
+        return Err(format!("updateOne 只支持普通表，{} 是视图或外部表", relation.name).into());
+    }
+    update(client, cache, relation, request, true).await
+}
+
+pub async fn update_many(
+    client: &Client,
+    cache: &StatementCache,
+    relation: &Relation,
+    request: &serde_json::Value,
+) -> Result<serde_json::Value, QueryError> {
+    update(client, cache, relation, request, false).await
+}
+
+async fn update(
+    client: &Client,
+    cache: &StatementCache,
+    relation: &Relation,
+    request: &serde_json::Value,
+    single: bool,
+) -> Result<serde_json::Value, QueryError> {
+    let document = request
+        .get("update")
+        .and_then(|v| v.as_object())
+        .ok_or("更新操作需要 update 对象")?;
+    let returning = request
+        .get("returning")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    if let Some(upsert) = upsert::upsert_of(relation, request)? {
+        return update_upsert(
+            client, cache, relation, request, document, &upsert, returning,
+        )
+        .await;
+    }
+
+    let mut params = Params::default();
+    let condition = build_condition(relation, &filter_of(request), &mut params)?;
+    let assignments = build_assignments(relation, document, &mut params, None)?;
+    let sql = build_update(
+        relation,
+        condition.as_deref(),
+        &assignments,
+        single,
+        returning,
+    );
+
+    let rows = query(client, cache, &sql, &params).await.map_err(|mut e| {
+        e.context = Some("更新失败".to_string());
+        e
+    })?;
+    let row = rows.first().ok_or("更新失败: 没有返回结果")?;
+    let matched: i64 = row.get("_matched");
+    let modified: i64 = row.get("_modified");
+    // 没有更新任何行时仍返回一行计数，其余列为空
+    let mut data = if returning && modified > 0 {
+        crate::rows_to_json(&rows)
+    } else {
+        Vec::new()
+    };
+    for row in &mut data {
+        if let Some(row) = row.as_object_mut() {
+            row.remove("_matched");
+            row.remove("_modified");
+        }
+    }
+
+    QueryResult {
+        data,
+        matched: Some(matched as u64),
+        modified: Some(modified as u64),
+        sql: Some(tidy_sql(&sql)),
+        ..Default::default()
+    }
+    .into_value()
+}
+
+/// `"upsert"` 时编译为 INSERT ... ON CONFLICT
+///
+/// filter 只能包含字段相等条件，这些字段与 `$set`、`$inc` 的值一起作为新行插入；
+/// 与已有行冲突时按 update 文档更新。是否冲突由冲突目标（主键或唯一约束）决定。
+async fn update_upsert(
+    client: &Client,
+    cache: &StatementCache,
+    relation: &Relation,
+    request: &serde_json::Value,
+    document: &serde_json::Map<String, serde_json::Value>,
+    upsert: &Upsert,
+    returning: bool,
+) -> Result<serde_json::Value, QueryError> {
+    if let Some(operator) = ["$push", "$pull"]
+        .into_iter()
+        .find(|operator| document.contains_key(*operator))
+    {
+        return Err(format!("upsert 不支持 {}：插入新行时没有可以修改的数组", operator).into());
+    }
+
+    let mut values = serde_json::Map::new();
+    for (key, value) in filter_of(request) {
+        let value = match value {
+            serde_json::Value::Object(mut operators) => match operators.remove("$eq") {
+                Some(value) if operators.is_empty() => value,
+                _ => return Err(upsert_filter_error(&key)),
+            },
+            value => value,
+        };
+        if key.starts_with('$') || value.is_null() {
+            return Err(upsert_filter_error(&key));
+        }
+        relation.column(&key)?;
+        values.insert(key, value);
+    }
+    // 新行取 $set 的值，$inc 的增量作为初始值
+    for operator in ["$set", "$inc"] {
+        if let Some(fields) = document.get(operator).and_then(|v| v.as_object()) {
+            for (key, value) in fields {
+                values.insert(key.clone(), value.clone());
+            }
+        }
+    }
+
+    let mut params = Params::default();
+    let mut columns = Vec::with_capacity(values.len());
+    let mut placeholders = Vec::with_capacity(values.len());
+    for (key, value) in &values {
+        let column = relation.column(key)?;
+        columns.push(quote_ident(&column.name));
+        placeholders.push(params.push_typed(value.clone(), column));
+    }
+
+    let available: Vec<&str> = values.keys().map(String::as_str).collect();
+    let conflict = upsert::conflict_columns(client, cache, relation, upsert, &available).await?;
+    let mut assignments = build_assignments(relation, document, &mut params, Some("existing"))?;
+    // 指定了 update 时只更新这些列，不在 update 文档中的列取新行的值
+    if let Some(update) = upsert.update() {
+        assignments = update
+            .iter()
+            .map(|name| {
+                let column = quote_ident(name);
+                match assignments.iter().find(|(existing, _)| *existing == column) {
+                    Some(assignment) => Ok(assignment.clone()),
+                    None if columns.contains(&column) => {
+                        Ok((column.clone(), format!("EXCLUDED.{}", column)))
+                    }
+                    None => Err(upsert::no_new_value(name)),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+    }
+
+    let sql = format!(
+        "INSERT INTO {} AS existing ({}) VALUES ({}){}{}",
+        relation.qualified(),
+        columns.join(", "),
+        placeholders.join(", "),
+        upsert::on_conflict(&conflict, &assignments),
+        upsert::RETURNING
+    );
+
+    let rows = query(client, cache, &sql, &params).await.map_err(|mut e| {
+        e.context = Some("更新失败".to_string());
+        e
+    })?;
+    let data = crate::rows_to_json(&rows);
+    let (inserted, updated) = upsert::count_rows(&data);
+    QueryResult {
+        skipped: Some(1 - data.len() as u64),
+        data: if returning { data } else { Vec::new() },
+        matched: Some(updated),
+        modified: Some(updated),
+        inserted: Some(inserted),
+        updated: Some(updated),
+        sql: Some(tidy_sql(&sql)),
+        ..Default::default()
+    }
+    .into_value()
+}
+
+fn upsert_filter_error(key: &str) -> QueryError {
+    format!(
+        "upsert 时 filter 只能包含字段相等条件（{} 不是），这些字段会作为新行的值",
+        key
+    )
+    .into()
+}
+
+/// 把更新文档编译为 (列, 新值表达式) 列表
+///
+/// `qualifier` 为表的别名，ON CONFLICT DO UPDATE 中引用旧值时必须带上，否则与 EXCLUDED 冲突。
+fn build_assignments(
+    relation: &Relation,
+    document: &serde_json::Map<String, serde_json::Value>,
+    params: &mut Params,
+    qualifier: Option<&str>,
+) -> Result<Vec<(String, String)>, QueryError> {
+    let mut assignments: Vec<(String, String)> = Vec::new();
+
+    for (operator, fields) in document {
+        if !operator.starts_with('$') {
+            return Err(format!(
+                "更新文档只能使用 $set、$inc 等运算符，不能直接写列 {}",
+                operator
+            )
+            .into());
+        }
+        let fields = fields
+            .as_object()
+            .ok_or_else(|| format!("{} 的值必须是对象", operator))?;
+
+        for (key, value) in fields {
+            let column = relation.column(key)?;
+            let target = quote_ident(&column.name);
+            let current = match qualifier {
+                Some(qualifier) => format!("{}.{}", qualifier, target),
+                None => target.clone(),
+            };
+            let expression = match operator.as_str() {
+                "$set" => params.push_typed(value.clone(), column),
+                "$inc" => {
+                    if !value.is_number() {
+                        return Err(format!("$inc 的值必须是数字: {}", key).into());
+                    }
+                    // 与 MongoDB 一样，空值按 0 累加
+                    format!(
+                        "coalesce({}, 0) + {}",
+                        current,
+                        params.push_typed(value.clone(), column)
+                    )
+                }
+                "$unset" => "NULL".to_string(),
+                "$push" | "$pull" => {
+                    let element_type = column.data_type.strip_suffix("[]").ok_or_else(|| {
+                        format!(
+                            "{} 只能用于数组列，{} 的类型是 {}",
+                            operator, key, column.data_type
+                        )
+                    })?;
+                    let function = if operator == "$push" {
+                        "array_append"
+                    } else {
+                        "array_remove"
+                    };
+                    format!(
+                        "{}({}, {}::{})",
+                        function,
+                        current,
+                        params.push(value.clone()),
+                        element_type
+                    )
+                }
+                _ => return Err(format!("不支持的更新运算符: {}", operator).into()),
+            };
+
+            if assignments.iter().any(|(existing, _)| *existing == target) {
+                return Err(format!("列 {} 在更新文档中出现了多次", key).into());
+            }
+            assignments.push((target, expression));
+        }
+    }
+
+    if assignments.is_empty() {
+        return Err("update 中没有要更新的列".into());
+    }
+    Ok(assignments)
+}
+
+/// 生成更新语句，同时统计匹配行数和实际修改的行数
+///
+/// 新值与旧值相同的行不更新，也不计入 modified。计数列为 `_matched` 和 `_modified`，
+/// `returning` 时与 RETURNING * 的行一起返回。
+fn build_update(
+    relation: &Relation,
+    condition: Option<&str>,
+    assignments: &[(String, String)],
+    single: bool,
+    returning: bool,
+) -> String {
+    let table = relation.qualified();
+    let where_clause = condition
+        .map(|c| format!(" WHERE {}", c))
+        .unwrap_or_default();
+
+    let set_list: Vec<String> = assignments
+        .iter()
+        .map(|(column, expression)| format!("{} = {}", column, expression))
+        .collect();
+    let columns: Vec<&str> = assignments.iter().map(|(c, _)| c.as_str()).collect();
+    let expressions: Vec<&str> = assignments.iter().map(|(_, e)| e.as_str()).collect();
+    let changed = format!(
+        "ROW({}) IS DISTINCT FROM ROW({})",
+        columns.join(", "),
+        expressions.join(", ")
+    );
+
+    let (matched, target) = if single {
+        (
+            format!(
+                "SELECT tableoid, ctid FROM {}{} LIMIT 1",
+                table, where_clause
+            ),
+            "(tableoid, ctid) = (SELECT tableoid, ctid FROM matched)".to_string(),
+        )
+    } else {
+        (
+            format!("SELECT 1 FROM {}{}", table, where_clause),
+            condition.unwrap_or("TRUE").to_string(),
+        )
+    };
+    let counts = "(SELECT count(*) FROM matched) AS \"_matched\", \
+                  (SELECT count(*) FROM updated) AS \"_modified\"";
+    let select = if returning {
+        // 更新后的行按原类型返回，计数附加在每一行上
+        format!(
+            "SELECT u.*, {} FROM updated u RIGHT JOIN (SELECT) AS counts ON TRUE",
+            counts
+        )
+    } else {
+        format!("SELECT {}", counts)
+    };
+
+    format!(
+        "WITH matched AS ({}), updated AS (UPDATE {} SET {} WHERE ({}) AND {} RETURNING *) {}",
+        matched,
+        table,
+        set_list.join(", "),
+        target,
+        changed,
+        select
+    )
+}
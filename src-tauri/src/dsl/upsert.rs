@@ -0,0 +1,158 @@
+//! upsert：INSERT ... ON CONFLICT
+//!
+//! `"upsert": true` 自动使用主键或唯一约束作为冲突目标，也可以写成
+//! `{"on": ["email"], "update": ["name"]}` 指定冲突列和冲突时更新的列，
+//! `update` 为空数组表示冲突时什么也不做（DO NOTHING），`update` 中的列必须有新值。
+//! RETURNING 附加 `_inserted`（`xmax = 0`），返回的每一行都带有该标记，插入和更新的行数由它统计。
+
+use super::Relation;
+use crate::error::QueryError;
+use crate::ident::quote_ident;
+use crate::stmt_cache::StatementCache;
+use tokio_postgres::Client;
+
+/// 附加在 RETURNING 中，新插入的行 xmax 为 0
+pub const RETURNING: &str = " RETURNING *, (xmax = 0) AS \"_inserted\"";
+
+pub struct Upsert {
+    on: Option<Vec<String>>,
+    update: Option<Vec<String>>,
+}
+
+impl Upsert {
+    /// 冲突时要更新的列，None 表示由调用方决定
+    pub fn update(&self) -> Option<&[String]> {
+        self.update.as_deref()
+    }
+}
+
+/// 读取请求中的 upsert 选项，没有或为 false 时返回 None
+pub fn upsert_of(
+    relation: &Relation,
+    request: &serde_json::Value,
+) -> Result<Option<Upsert>, QueryError> {
+    let upsert = match request.get("upsert") {
+        None | Some(serde_json::Value::Null) | Some(serde_json::Value::Bool(false)) => {
+            return Ok(None)
+        }
+        Some(serde_json::Value::Bool(true)) => Upsert {
+            on: None,
+            update: None,
+        },
+        Some(serde_json::Value::Object(options)) => Upsert {
+            on: column_list(relation, options.get("on"), "upsert.on")?,
+            update: column_list(relation, options.get("update"), "upsert.update")?,
+        },
+        Some(_) => return Err("upsert 必须是 true 或 {\"on\": [...], \"update\": [...]}".into()),
+    };
+    if !relation.is_table {
+        return Err(format!("upsert 只支持普通表，{} 是视图或外部表", relation.name).into());
+    }
+    if matches!(&upsert.on, Some(on) if on.is_empty()) {
+        return Err("upsert.on 不能为空".into());
+    }
+    Ok(Some(upsert))
+}
+
+fn column_list(
+    relation: &Relation,
+    value: Option<&serde_json::Value>,
+    option: &str,
+) -> Result<Option<Vec<String>>, QueryError> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let items = value
+        .as_array()
+        .ok_or_else(|| format!("{} 必须是列名数组", option))?;
+    let mut columns = Vec::with_capacity(items.len());
+    for item in items {
+        let name = item
+            .as_str()
+            .ok_or_else(|| format!("{} 必须是列名数组", option))?;
+        columns.push(relation.column(name)?.name.clone());
+    }
+    Ok(Some(columns))
+}
+
+/// 冲突目标列
+///
+/// 未指定时依次尝试主键和唯一约束（不含部分索引和表达式索引），
+/// 选第一个所有列都在 `available` 中的。
+pub async fn conflict_columns(
+    client: &Client,
+    cache: &StatementCache,
+    relation: &Relation,
+    upsert: &Upsert,
+    available: &[&str],
+) -> Result<Vec<String>, QueryError> {
+    if let Some(on) = &upsert.on {
+        if let Some(missing) = on.iter().find(|c| !available.contains(&c.as_str())) {
+            return Err(format!("upsert 的冲突列 {} 必须出现在文档中", missing).into());
+        }
+        return Ok(on.clone());
+    }
+
+    let rows = cache
+        .query(
+            client,
+            "SELECT array_agg(a.attname::text ORDER BY k.ord)
+             FROM pg_index i
+             CROSS JOIN LATERAL unnest(i.indkey::int2[]) WITH ORDINALITY AS k(attnum, ord)
+             JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = k.attnum
+             WHERE i.indrelid = $1::text::regclass
+               AND i.indisunique AND i.indimmediate
+               AND i.indpred IS NULL AND i.indexprs IS NULL
+             GROUP BY i.indexrelid, i.indisprimary
+             ORDER BY i.indisprimary DESC, i.indexrelid",
+            &[&relation.qualified()],
+        )
+        .await
+        .map_err(|e| QueryError::from_pg("查询约束信息失败", &e))?;
+
+    rows.iter()
+        .map(|row| row.get::<_, Vec<String>>(0))
+        .find(|columns| columns.iter().all(|c| available.contains(&c.as_str())))
+        .ok_or_else(|| {
+            format!(
+                "{} 没有可用于 upsert 的主键或唯一约束（约束的列必须都出现在文档中），请用 upsert.on 指定",
+                relation.name
+            )
+            .into()
+        })
+}
+
+/// ` ON CONFLICT (...) DO UPDATE SET ...`，没有要更新的列时为 DO NOTHING
+pub fn on_conflict(conflict: &[String], assignments: &[(String, String)]) -> String {
+    let target: Vec<String> = conflict.iter().map(|c| quote_ident(c)).collect();
+    if assignments.is_empty() {
+        return format!(" ON CONFLICT ({}) DO NOTHING", target.join(", "));
+    }
+    let set_list: Vec<String> = assignments
+        .iter()
+        .map(|(column, expression)| format!("{} = {}", column, expression))
+        .collect();
+    format!(
+        " ON CONFLICT ({}) DO UPDATE SET {}",
+        target.join(", "),
+        set_list.join(", ")
+    )
+}
+
+/// `update` 中的列既不在插入的列中、也不在更新文档中时，冲突时没有新值可用
+pub fn no_new_value(column: &str) -> QueryError {
+    format!(
+        "upsert.update 中的列 {} 没有新值，冲突时会被改为默认值，请在文档中提供该列",
+        column
+    )
+    .into()
+}
+
+/// 按每行的 `_inserted` 统计插入和更新的行数
+pub fn count_rows(data: &[serde_json::Value]) -> (u64, u64) {
+    let inserted = data
+        .iter()
+        .filter(|row| row.get("_inserted").and_then(|v| v.as_bool()) == Some(true))
+        .count() as u64;
+    (inserted, data.len() as u64 - inserted)
+}
@@ -0,0 +1,176 @@
+use serde::Serialize;
+use std::fmt;
+
+/// 返回给前端的结构化错误
+///
+/// 数据库错误会带上 SQLSTATE、严重级别、详情、提示以及出错位置，
+/// 其它错误（未连接、参数错误等）只有 `message`。
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct QueryError {
+    /// 错误发生的阶段，例如 "查询失败"、"执行失败"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    /// SQLSTATE 错误码，例如 42P01
+    pub code: Option<String>,
+    pub message: String,
+    /// 出错位置，已换算为整段 SQL 文本中的行列
+    pub position: Option<ErrorLocation>,
+    /// 数据库返回的其它字段，展开到同一层级
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub details: Option<Box<ErrorDetails>>,
+    /// 数据库返回的原始位置（语句内从 1 开始的字符序号）
+    #[serde(skip)]
+    raw_position: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ErrorDetails {
+    pub severity: String,
+    pub detail: Option<String>,
+    pub hint: Option<String>,
+    /// 函数体等内部查询中的出错位置
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub internal_position: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub internal_query: Option<String>,
+    pub schema: Option<String>,
+    pub table: Option<String>,
+    pub column: Option<String>,
+    pub datatype: Option<String>,
+    pub constraint: Option<String>,
+    /// 因 timeout_ms / lock_timeout_ms 超时而失败时标明超时类型
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<TimeoutKind>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeoutKind {
+    /// 超过 statement_timeout（SQLSTATE 57014）
+    Statement,
+    /// 等锁超过 lock_timeout（SQLSTATE 55P03）
+    Lock,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ErrorLocation {
+    /// 从 0 开始的字符偏移
+    pub offset: usize,
+    /// 从 1 开始的行号
+    pub line: usize,
+    /// 从 1 开始的列号
+    pub column: usize,
+}
+
+impl QueryError {
+    pub fn new(message: impl Into<String>) -> Self {
+        QueryError {
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+
+    /// 从 tokio_postgres 错误构建，`context` 说明失败的阶段
+    pub fn from_pg(context: &str, err: &tokio_postgres::Error) -> Self {
+        let db = match err.as_db_error() {
+            Some(db) => db,
+            None => {
+                return QueryError {
+                    context: Some(context.to_string()),
+                    message: err.to_string(),
+                    ..Default::default()
+                }
+            }
+        };
+
+        let (raw_position, internal_position, internal_query) = match db.position() {
+            Some(tokio_postgres::error::ErrorPosition::Original(pos)) => (Some(*pos), None, None),
+            Some(tokio_postgres::error::ErrorPosition::Internal { position, query }) => {
+                (None, Some(*position), Some(query.clone()))
+            }
+            None => (None, None, None),
+        };
+
+        QueryError {
+            context: Some(context.to_string()),
+            code: Some(db.code().code().to_string()),
+            message: db.message().to_string(),
+            position: None,
+            details: Some(Box::new(ErrorDetails {
+                severity: db.severity().to_string(),
+                detail: db.detail().map(str::to_string),
+                hint: db.hint().map(str::to_string),
+                internal_position,
+                internal_query,
+                schema: db.schema().map(str::to_string),
+                table: db.table().map(str::to_string),
+                column: db.column().map(str::to_string),
+                datatype: db.datatype().map(str::to_string),
+                constraint: db.constraint().map(str::to_string),
+                timeout: None,
+            })),
+            raw_position,
+        }
+    }
+
+    /// 换算数据库返回的原始位置，用于语句执行前被改写过的情况（如替换了变量）
+    pub fn map_position(mut self, map: impl FnOnce(u32) -> u32) -> Self {
+        self.raw_position = self.raw_position.map(map);
+        self
+    }
+
+    /// 将数据库返回的位置换算为 `source` 中的行列
+    ///
+    /// `statement_offset` 为出错语句在 `source` 中的字节偏移，
+    /// 多语句脚本按分号拆分后执行时需要它来定位到原文。
+    pub fn locate(mut self, source: &str, statement_offset: usize) -> Self {
+        if let Some(pos) = self.raw_position {
+            let prefix_chars = source
+                .get(..statement_offset)
+                .map(|s| s.chars().count())
+                .unwrap_or(0);
+            let offset = prefix_chars + (pos as usize).saturating_sub(1);
+            self.position = Some(char_offset_to_location(source, offset));
+        }
+        self
+    }
+}
+
+fn char_offset_to_location(source: &str, offset: usize) -> ErrorLocation {
+    let mut line = 1;
+    let mut column = 1;
+    for ch in source.chars().take(offset) {
+        if ch == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+    ErrorLocation {
+        offset,
+        line,
+        column,
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.context {
+            Some(context) => write!(f, "{}: {}", context, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl From<String> for QueryError {
+    fn from(message: String) -> Self {
+        QueryError::new(message)
+    }
+}
+
+impl From<&str> for QueryError {
+    fn from(message: &str) -> Self {
+        QueryError::new(message)
+    }
+}
@@ -0,0 +1,507 @@
+//! SQL 格式化
+//!
+//! 基于词法单元重排空白：子句换行、列表逐项换行、子查询和 CTE 缩进。
+//! 注释、字符串和美元引用的函数体原样保留，不改变语句含义。
+
+use crate::lexer::{self, Token, TokenKind, KEYWORDS};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeywordCase {
+    #[default]
+    Upper,
+    Lower,
+    /// 保持原样
+    Preserve,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FormatOptions {
+    /// 每级缩进的空格数
+    pub indent: usize,
+    pub keyword_case: KeywordCase,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            indent: 2,
+            keyword_case: KeywordCase::Upper,
+        }
+    }
+}
+
+/// 子句的排版方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Layout {
+    /// 子句内容另起一行缩进，逗号后换行（SELECT、FROM 等）
+    List,
+    /// 子句内容另起一行缩进，AND / OR 前换行（WHERE、HAVING）
+    Condition,
+    /// 内容跟在关键字后面（LIMIT、INSERT INTO 等）
+    Inline,
+}
+
+/// 一层缩进上下文：语句本身或括号中的子查询
+struct Frame {
+    /// 子句关键字所在的缩进级别
+    base: usize,
+    layout: Layout,
+    /// 该层内未闭合的普通括号数
+    parens: usize,
+    /// 是否为子查询括号
+    subquery: bool,
+    /// 已输出过有效内容
+    started: bool,
+    /// 出现过 UPDATE，之后的 SET 视为子句
+    saw_update: bool,
+    /// BETWEEN 之后的 AND 不换行
+    between: bool,
+    /// CASE 表达式中的 AND / OR 不换行
+    case_depth: usize,
+}
+
+impl Frame {
+    fn new(base: usize, subquery: bool) -> Self {
+        Frame {
+            base,
+            layout: Layout::Inline,
+            parens: 0,
+            subquery,
+            started: false,
+            saw_update: false,
+            between: false,
+            case_depth: 0,
+        }
+    }
+}
+
+struct Formatter<'a> {
+    options: &'a FormatOptions,
+    out: String,
+    frames: Vec<Frame>,
+    /// 上一个输出的有效词法单元
+    prev: Option<Token<'a>>,
+    /// 子句关键字之后，内容需要另起一行
+    pending_content: bool,
+    /// 下一个词法单元前不加空格（一元运算符之后）
+    glue_next: bool,
+    /// 方括号深度，其中的运算符两侧不加空格
+    brackets: usize,
+}
+
+/// 格式化 SQL，多条语句之间空一行
+pub fn format_sql(sql: &str, options: &FormatOptions) -> String {
+    let tokens = lexer::tokenize(sql);
+    let mut formatter = Formatter {
+        options,
+        out: String::with_capacity(sql.len() + sql.len() / 4),
+        frames: vec![Frame::new(0, false)],
+        prev: None,
+        pending_content: false,
+        glue_next: false,
+        brackets: 0,
+    };
+
+    for (i, token) in tokens.iter().enumerate() {
+        if token.kind == TokenKind::Whitespace {
+            continue;
+        }
+        let newline_before = i == 0
+            || tokens[..i]
+                .iter()
+                .rev()
+                .take_while(|t| t.kind == TokenKind::Whitespace)
+                .any(|t| t.text.contains('\n'));
+        let space_before = i > 0 && tokens[i - 1].kind == TokenKind::Whitespace;
+        let next = tokens[i + 1..].iter().find(|t| !t.is_trivia());
+        formatter.token(*token, newline_before, space_before, next);
+    }
+
+    let mut out = formatter.out;
+    let trimmed = out.trim_end().len();
+    out.truncate(trimmed);
+    out
+}
+
+impl<'a> Formatter<'a> {
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("根层级始终存在")
+    }
+
+    /// 另起一行，已在行首时只调整缩进
+    fn newline(&mut self, level: usize) {
+        let trimmed = self.out.trim_end_matches(' ').len();
+        self.out.truncate(trimmed);
+        if !self.out.is_empty() && !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+        let width = level * self.options.indent;
+        self.out.push_str(&" ".repeat(width));
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.out.is_empty() || self.out.trim_end_matches(' ').ends_with('\n')
+    }
+
+    fn push(&mut self, text: &str, space: bool) {
+        if space && !self.at_line_start() && !self.glue_next {
+            self.out.push(' ');
+        }
+        self.glue_next = false;
+        self.out.push_str(text);
+    }
+
+    fn keyword_text(&self, token: &Token<'a>) -> String {
+        let upper = token.text.to_uppercase();
+        let adjacent_dot = self.prev.is_some_and(|p| p.text == ".");
+        if adjacent_dot || !KEYWORDS.contains(&upper.as_str()) {
+            return token.text.to_string();
+        }
+        match self.options.keyword_case {
+            KeywordCase::Upper => upper,
+            KeywordCase::Lower => token.text.to_lowercase(),
+            KeywordCase::Preserve => token.text.to_string(),
+        }
+    }
+
+    fn prev_word(&self) -> Option<String> {
+        self.prev
+            .filter(|p| p.kind == TokenKind::Word)
+            .map(|p| p.text.to_uppercase())
+    }
+
+    fn token(
+        &mut self,
+        token: Token<'a>,
+        newline_before: bool,
+        space_before: bool,
+        next: Option<&Token<'a>>,
+    ) {
+        match token.kind {
+            TokenKind::LineComment | TokenKind::BlockComment => {
+                self.comment(token, newline_before);
+                return;
+            }
+            TokenKind::Semicolon => {
+                self.push(";", false);
+                self.out.push_str("\n\n");
+                self.frames = vec![Frame::new(0, false)];
+                self.pending_content = false;
+                self.brackets = 0;
+                self.prev = None;
+                return;
+            }
+            _ => {}
+        }
+
+        let upper = token.text.to_uppercase();
+        let is_word = token.kind == TokenKind::Word;
+        // 紧跟括号的词是函数调用（如 left(...)），不当作子句
+        let is_call =
+            next.is_some_and(|n| n.text == "(" && n.offset == token.offset + token.text.len());
+
+        if is_word && !is_call && self.frame().parens == 0 && self.clause(&upper, next) {
+            self.emit_word(token, true);
+            return;
+        }
+
+        if self.pending_content && !self.holds_content_line(&upper) {
+            self.pending_content = false;
+            let base = self.frame().base;
+            self.newline(base + 1);
+        }
+
+        match token.text {
+            "(" => {
+                // 子查询和 CTE 中的写语句都另起缩进
+                let subquery = next.is_some_and(|n| {
+                    matches!(
+                        n.text.to_uppercase().as_str(),
+                        "SELECT" | "WITH" | "VALUES" | "INSERT" | "UPDATE" | "DELETE"
+                    )
+                });
+                // 函数调用保持原样紧贴，IN、AS 等关键字后加空格
+                let space = space_before
+                    || self.prev_word().is_some_and(|w| {
+                        matches!(w.as_str(), "IN" | "AS" | "ON" | "USING" | "OVER" | "VALUES")
+                    });
+                self.push("(", space && !self.prev_is_open());
+                if subquery {
+                    let level = self.current_indent() + 1;
+                    self.frames.push(Frame::new(level, true));
+                } else {
+                    self.frame().parens += 1;
+                }
+            }
+            ")" => {
+                if self.frame().parens == 0 && self.frame().subquery {
+                    self.frames.pop();
+                    self.pending_content = false;
+                    let level = self.frames_base_for_close();
+                    self.newline(level);
+                    self.push(")", false);
+                } else {
+                    let frame = self.frame();
+                    frame.parens = frame.parens.saturating_sub(1);
+                    self.push(")", false);
+                }
+            }
+            "[" => {
+                self.brackets += 1;
+                self.push("[", false);
+            }
+            "]" => {
+                self.brackets = self.brackets.saturating_sub(1);
+                self.push("]", false);
+            }
+            "," => {
+                self.push(",", false);
+                let frame = self.frame();
+                if frame.parens == 0 && frame.layout == Layout::List {
+                    let base = frame.base;
+                    self.newline(base + 1);
+                }
+            }
+            "." => self.push(".", false),
+            _ if token.kind == TokenKind::Operator => self.operator(token),
+            _ if is_word => {
+                let frame = self.frame();
+                if upper == "CASE" {
+                    frame.case_depth += 1;
+                } else if upper == "END" {
+                    frame.case_depth = frame.case_depth.saturating_sub(1);
+                }
+                if frame.parens == 0 && frame.case_depth == 0 && frame.layout == Layout::Condition {
+                    if upper == "BETWEEN" {
+                        frame.between = true;
+                    } else if upper == "AND" && frame.between {
+                        frame.between = false;
+                    } else if upper == "AND" || upper == "OR" {
+                        let base = frame.base;
+                        self.newline(base + 1);
+                    }
+                }
+                let space = !self.prev_is_open();
+                self.emit_word(token, space);
+            }
+            _ => {
+                let space = !self.prev_is_open();
+                self.push(token.text, space);
+                self.frame().started = true;
+                self.prev = Some(token);
+            }
+        }
+        if matches!(token.text, "(" | ")" | "[" | "]" | "," | ".") {
+            self.frame().started = true;
+            self.prev = Some(token);
+        }
+    }
+
+    fn emit_word(&mut self, token: Token<'a>, space: bool) {
+        let text = self.keyword_text(&token);
+        self.push(&text, space);
+        self.frame().started = true;
+        self.prev = Some(token);
+    }
+
+    /// 上一个词法单元之后不应加空格
+    fn prev_is_open(&self) -> bool {
+        self.prev
+            .is_some_and(|p| matches!(p.text, "(" | "[" | "." | "::"))
+    }
+
+    /// 当前行的缩进级别
+    fn current_indent(&self) -> usize {
+        let line = self.out.rsplit('\n').next().unwrap_or("");
+        let spaces = line.len() - line.trim_start_matches(' ').len();
+        spaces / self.options.indent.max(1)
+    }
+
+    /// 子查询闭合括号所在的缩进级别
+    fn frames_base_for_close(&self) -> usize {
+        let frame = self.frames.last().expect("根层级始终存在");
+        match frame.layout {
+            Layout::Inline => frame.base,
+            _ => frame.base + 1,
+        }
+    }
+
+    /// 子句关键字之后仍留在同一行的词，如 SELECT DISTINCT、GROUP BY
+    fn holds_content_line(&self, upper: &str) -> bool {
+        let prev = self.prev_word();
+        match upper {
+            "DISTINCT" | "ALL" => prev.as_deref() == Some("SELECT"),
+            "BY" => matches!(prev.as_deref(), Some("GROUP") | Some("ORDER")),
+            _ => false,
+        }
+    }
+
+    /// 识别子句关键字并换行，返回 true 表示已处理换行和排版
+    fn clause(&mut self, upper: &str, next: Option<&Token<'a>>) -> bool {
+        let prev = self.prev_word();
+        let prev = prev.as_deref();
+        let started = self.frame().started;
+        let saw_update = self.frame().saw_update;
+        let next_upper = next.map(|n| n.text.to_uppercase());
+
+        // ON CONFLICT DO UPDATE 中的 UPDATE 不换行，但之后的 SET 仍是子句
+        if upper == "UPDATE" && prev == Some("DO") {
+            self.frame().saw_update = true;
+            return false;
+        }
+
+        let (layout, indent_offset) = match upper {
+            "SELECT" => (Layout::List, 0),
+            "FROM" if !matches!(prev, Some("DELETE") | Some("DISTINCT")) => (Layout::List, 0),
+            "WHERE" | "HAVING" => (Layout::Condition, 0),
+            "GROUP" | "ORDER" if next_upper.as_deref() == Some("BY") => (Layout::List, 0),
+            "VALUES" => (Layout::List, 0),
+            "SET" if saw_update => (Layout::List, 0),
+            "RETURNING" | "LIMIT" | "OFFSET" | "FETCH" | "WINDOW" => (Layout::Inline, 0),
+            "ON" if next_upper.as_deref() == Some("CONFLICT") => (Layout::Inline, 0),
+            "FOR"
+                if matches!(
+                    next_upper.as_deref(),
+                    Some("UPDATE") | Some("SHARE") | Some("NO") | Some("KEY")
+                ) =>
+            {
+                (Layout::Inline, 0)
+            }
+            "UNION" | "INTERSECT" | "EXCEPT" => (Layout::Inline, 0),
+            "WITH" if !started => (Layout::Inline, 0),
+            "INSERT" | "DELETE" if !matches!(prev, Some("ON")) => (Layout::Inline, 0),
+            "UPDATE" if !matches!(prev, Some("ON") | Some("FOR")) => {
+                self.frame().saw_update = true;
+                (Layout::Inline, 0)
+            }
+            "JOIN"
+                if !matches!(
+                    prev,
+                    Some("LEFT")
+                        | Some("RIGHT")
+                        | Some("FULL")
+                        | Some("INNER")
+                        | Some("CROSS")
+                        | Some("NATURAL")
+                        | Some("OUTER")
+                ) =>
+            {
+                (Layout::List, 1)
+            }
+            "LEFT" | "RIGHT" | "FULL" | "INNER" | "CROSS"
+                if prev != Some("NATURAL")
+                    && matches!(next_upper.as_deref(), Some("JOIN") | Some("OUTER")) =>
+            {
+                (Layout::List, 1)
+            }
+            "NATURAL" => (Layout::List, 1),
+            _ => return false,
+        };
+
+        let base = self.frame().base;
+        self.newline(base + indent_offset);
+        self.pending_content = indent_offset == 0 && layout != Layout::Inline;
+        let frame = self.frame();
+        frame.between = false;
+        // JOIN 所在行仍属于 FROM 列表，保持列表排版
+        if indent_offset == 0 {
+            frame.layout = layout;
+        }
+        true
+    }
+
+    fn operator(&mut self, token: Token<'a>) {
+        if token.text == "::" || self.brackets > 0 {
+            self.push(token.text, false);
+            self.glue_next = true;
+            self.prev = Some(token);
+            return;
+        }
+        // 一元运算符：前面是运算符、左括号、逗号、关键字或行首
+        let unary = matches!(token.text, "-" | "+")
+            && match self.prev {
+                None => true,
+                Some(p) => {
+                    matches!(p.kind, TokenKind::Operator)
+                        || matches!(p.text, "(" | "," | "[")
+                        || (p.kind == TokenKind::Word
+                            && KEYWORDS.contains(&p.text.to_uppercase().as_str()))
+                }
+            };
+        let space = !self.prev_is_open();
+        self.push(token.text, space);
+        self.glue_next = unary;
+        self.frame().started = true;
+        self.prev = Some(token);
+    }
+
+    /// 注释原样输出；原本独占一行的注释仍独占一行
+    fn comment(&mut self, token: Token<'a>, newline_before: bool) {
+        if newline_before && !self.at_line_start() {
+            let level = if self.pending_content {
+                self.frame().base + 1
+            } else {
+                self.current_indent()
+            };
+            self.newline(level);
+        }
+        self.push(token.text.trim_end(), true);
+        if token.kind == TokenKind::LineComment {
+            let level = self.current_indent();
+            self.newline(level);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(sql: &str) -> String {
+        format_sql(sql, &FormatOptions::default())
+    }
+
+    #[test]
+    fn clauses_and_conditions() {
+        assert_eq!(
+            format("select a, b from t where x = 1 and y between 1 and 2 order by a limit 10"),
+            "SELECT\n  a,\n  b\nFROM\n  t\nWHERE\n  x = 1\n  AND y BETWEEN 1 AND 2\nORDER BY\n  a\nLIMIT 10"
+        );
+    }
+
+    #[test]
+    fn indents_cte_and_subquery() {
+        assert_eq!(
+            format("with c as (select id from u) select * from c where id in (select id from v)"),
+            "WITH c AS (\n  SELECT\n    id\n  FROM\n    u\n)\nSELECT\n  *\nFROM\n  c\nWHERE\n  id IN (\n    SELECT\n      id\n    FROM\n      v\n  )"
+        );
+    }
+
+    #[test]
+    fn keeps_literals_bodies_and_comments() {
+        let formatted =
+            format("create function f() returns int as $$ select  1 ;  $$ language sql");
+        assert!(formatted.starts_with("CREATE FUNCTION"));
+        assert!(formatted.contains("$$ select  1 ;  $$"));
+
+        assert_eq!(
+            format("select U&'d\\0061t' uescape '!', 'keep  this' -- note\nfrom t"),
+            "SELECT\n  U&'d\\0061t' uescape '!',\n  'keep  this' -- note\nFROM\n  t"
+        );
+    }
+
+    #[test]
+    fn keyword_case_and_indent() {
+        let options = FormatOptions {
+            indent: 4,
+            keyword_case: KeywordCase::Lower,
+        };
+        assert_eq!(
+            format_sql("SELECT A FROM T", &options),
+            "select\n    A\nfrom\n    T"
+        );
+    }
+}
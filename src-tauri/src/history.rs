@@ -0,0 +1,227 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+/// 历史记录文件名，位于应用数据目录下，每行一条 JSON 记录
+const HISTORY_FILE: &str = "query_history.jsonl";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: u64,
+    /// 连接标识，形如 user@host:port
+    pub connection: String,
+    pub database: String,
+    pub sql: String,
+    pub duration_ms: u64,
+    pub row_count: Option<u64>,
+    pub status: HistoryStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub timestamp: DateTime<Utc>,
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryStatus {
+    Success,
+    Error,
+}
+
+/// 历史搜索条件，所有字段均可省略
+#[derive(Debug, Default, Deserialize)]
+pub struct HistoryFilter {
+    /// SQL 文本包含的内容（不区分大小写）
+    pub text: Option<String>,
+    pub connection: Option<String>,
+    pub database: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub pinned_only: bool,
+    pub limit: Option<usize>,
+}
+
+/// 查询历史，内存中保存全部记录，新增记录以追加方式写入文件
+///
+/// 启动时同步加载；之后的文件读写都是异步的，不阻塞 Tauri 命令所在的运行时线程。
+#[derive(Default)]
+pub struct QueryHistory {
+    path: Option<PathBuf>,
+    entries: Vec<HistoryEntry>,
+    next_id: u64,
+}
+
+impl QueryHistory {
+    /// 从应用数据目录加载历史，文件不存在时返回空历史
+    pub fn load(dir: PathBuf) -> Self {
+        let path = dir.join(HISTORY_FILE);
+        let entries: Vec<HistoryEntry> = match File::open(&path) {
+            Ok(file) => BufReader::new(file)
+                .lines()
+                .map_while(Result::ok)
+                // 跳过损坏的行，避免一条坏记录导致整个历史不可用
+                .filter_map(|line| serde_json::from_str(&line).ok())
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        let next_id = entries.iter().map(|e| e.id).max().map_or(1, |id| id + 1);
+
+        QueryHistory {
+            path: Some(path),
+            entries,
+            next_id,
+        }
+    }
+
+    /// 追加一条记录，`entry.id` 会被重新分配
+    pub async fn record(&mut self, mut entry: HistoryEntry) -> Result<u64, String> {
+        entry.id = self.next_id;
+        self.next_id += 1;
+
+        if let Some(path) = &self.path {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .await
+                    .map_err(|e| format!("创建历史目录失败: {}", e))?;
+            }
+            let mut line =
+                serde_json::to_string(&entry).map_err(|e| format!("序列化失败: {}", e))?;
+            line.push('\n');
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await
+                .map_err(|e| format!("打开历史文件失败: {}", e))?;
+            file.write_all(line.as_bytes())
+                .await
+                .map_err(|e| format!("写入历史失败: {}", e))?;
+        }
+
+        let id = entry.id;
+        self.entries.push(entry);
+        Ok(id)
+    }
+
+    /// 按条件搜索，结果按时间倒序
+    pub fn search(&self, filter: &HistoryFilter) -> Vec<HistoryEntry> {
+        let text = filter.text.as_ref().map(|t| t.to_lowercase());
+
+        self.entries
+            .iter()
+            .rev()
+            .filter(|e| !filter.pinned_only || e.pinned)
+            .filter(|e| {
+                filter
+                    .connection
+                    .as_ref()
+                    .is_none_or(|c| &e.connection == c)
+            })
+            .filter(|e| filter.database.as_ref().is_none_or(|d| &e.database == d))
+            .filter(|e| filter.from.is_none_or(|from| e.timestamp >= from))
+            .filter(|e| filter.to.is_none_or(|to| e.timestamp <= to))
+            .filter(|e| {
+                text.as_ref()
+                    .is_none_or(|t| e.sql.to_lowercase().contains(t.as_str()))
+            })
+            .take(filter.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+
+    /// 设置置顶状态，返回记录是否存在
+    pub async fn set_pinned(&mut self, id: u64, pinned: bool) -> Result<bool, String> {
+        match self.entries.iter_mut().find(|e| e.id == id) {
+            Some(entry) => entry.pinned = pinned,
+            None => return Ok(false),
+        }
+        self.rewrite().await?;
+        Ok(true)
+    }
+
+    /// 清理旧记录，置顶的记录始终保留
+    ///
+    /// `before` 删除该时间之前的记录；`keep_latest` 只保留最近的 N 条未置顶记录。
+    /// 返回删除的条数。
+    pub async fn purge(
+        &mut self,
+        before: Option<DateTime<Utc>>,
+        keep_latest: Option<usize>,
+    ) -> Result<usize, String> {
+        let original = self.entries.len();
+
+        if let Some(before) = before {
+            self.entries.retain(|e| e.pinned || e.timestamp >= before);
+        }
+
+        if let Some(keep) = keep_latest {
+            let unpinned = self.entries.iter().filter(|e| !e.pinned).count();
+            let mut to_remove = unpinned.saturating_sub(keep);
+            // 记录按时间顺序追加，从头部开始删除最旧的
+            self.entries.retain(|e| {
+                if !e.pinned && to_remove > 0 {
+                    to_remove -= 1;
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+
+        let removed = original - self.entries.len();
+        if removed > 0 {
+            self.rewrite().await?;
+        }
+        Ok(removed)
+    }
+
+    /// 用内存中的记录重写历史文件
+    async fn rewrite(&self) -> Result<(), String> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let mut content = String::new();
+        for entry in &self.entries {
+            content
+                .push_str(&serde_json::to_string(entry).map_err(|e| format!("序列化失败: {}", e))?);
+            content.push('\n');
+        }
+
+        // 先写临时文件再替换，避免写入中途失败丢失历史
+        let tmp = path.with_extension("jsonl.tmp");
+        fs::write(&tmp, content)
+            .await
+            .map_err(|e| format!("写入历史失败: {}", e))?;
+        fs::rename(&tmp, path)
+            .await
+            .map_err(|e| format!("写入历史失败: {}", e))?;
+        Ok(())
+    }
+}
+
+/// 从查询结果中提取行数：单条结果取 rows_affected / data 长度 / total，多条结果求和
+pub fn result_row_count(result: &serde_json::Value) -> Option<u64> {
+    match result {
+        serde_json::Value::Array(items) => items.iter().map(result_row_count).sum(),
+        serde_json::Value::Object(obj) => obj
+            .get("rows_affected")
+            .and_then(|v| v.as_u64())
+            .or_else(|| {
+                obj.get("data")
+                    .and_then(|v| v.as_array())
+                    .filter(|data| !data.is_empty())
+                    .map(|data| data.len() as u64)
+            })
+            .or_else(|| obj.get("total").and_then(|v| v.as_u64()))
+            .or_else(|| obj.get("data").map(|_| 0)),
+        _ => None,
+    }
+}
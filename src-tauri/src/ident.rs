@@ -0,0 +1,19 @@
+//! 标识符与字面量引用
+
+/// 用双引号引用标识符，内部的双引号加倍转义
+pub fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// 引用可能带 schema 前缀的名称，如 `public.users` -> `"public"."users"`
+pub fn quote_qualified(name: &str) -> String {
+    name.split('.')
+        .map(quote_ident)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// 用单引号引用字符串字面量，内部的单引号加倍转义
+pub fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
@@ -0,0 +1,342 @@
+use crate::copy::{self, CopyOptions};
+use crate::error::QueryError;
+use crate::script;
+use crate::stmt_cache::StatementCache;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter};
+use tokio::task::AbortHandle;
+use tokio_postgres::{CancelToken, Client, Config, NoTls};
+
+/// 任务进度事件名
+pub const JOB_PROGRESS_EVENT: &str = "job-progress";
+/// 任务结束（成功、失败或取消）事件名
+pub const JOB_FINISHED_EVENT: &str = "job-finished";
+
+/// 最多保留的已结束任务数，超出时丢弃最早的
+const MAX_FINISHED_JOBS: usize = 100;
+
+/// 后台任务请求，`kind` 决定执行方式
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum JobRequest {
+    /// 与 execute_query 相同的查询（SQL 或 JSON）
+    Query { query: serde_json::Value },
+    /// 多语句脚本
+    Script {
+        sql: String,
+        #[serde(default)]
+        continue_on_error: bool,
+    },
+    /// COPY 导入本地文件
+    Import { options: CopyOptions },
+    /// COPY 导出到本地文件
+    Export { options: CopyOptions },
+}
+
+impl JobRequest {
+    fn kind(&self) -> &'static str {
+        match self {
+            JobRequest::Query { .. } => "query",
+            JobRequest::Script { .. } => "script",
+            JobRequest::Import { .. } => "import",
+            JobRequest::Export { .. } => "export",
+        }
+    }
+
+    fn description(&self) -> String {
+        match self {
+            JobRequest::Query { query } => match query.get("sql").and_then(|v| v.as_str()) {
+                Some(sql) => sql.to_string(),
+                None => query.to_string(),
+            },
+            JobRequest::Script { sql, .. } => sql.clone(),
+            JobRequest::Import { options } | JobRequest::Export { options } => options.path.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    pub id: u64,
+    pub kind: &'static str,
+    pub description: String,
+    pub status: JobStatus,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// 最近一次进度
+    pub progress: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<QueryError>,
+}
+
+impl JobInfo {
+    /// 不含结果的摘要，用于列表和事件
+    fn summary(&self) -> JobInfo {
+        JobInfo {
+            id: self.id,
+            kind: self.kind,
+            description: self.description.clone(),
+            status: self.status,
+            started_at: self.started_at,
+            finished_at: self.finished_at,
+            progress: self.progress.clone(),
+            result: None,
+            error: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct JobProgress {
+    id: u64,
+    progress: serde_json::Value,
+}
+
+struct JobEntry {
+    info: JobInfo,
+    abort: Option<AbortHandle>,
+    cancel_token: Option<CancelToken>,
+}
+
+/// 后台任务登记表
+///
+/// 使用同步锁：进度回调是同步闭包，且临界区内没有 await。
+#[derive(Default)]
+pub struct JobRegistry {
+    next_id: u64,
+    jobs: BTreeMap<u64, JobEntry>,
+}
+
+pub type SharedJobs = Arc<Mutex<JobRegistry>>;
+
+impl JobRegistry {
+    fn insert(&mut self, request: &JobRequest) -> u64 {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.jobs.insert(
+            id,
+            JobEntry {
+                info: JobInfo {
+                    id,
+                    kind: request.kind(),
+                    description: request.description(),
+                    status: JobStatus::Running,
+                    started_at: Utc::now(),
+                    finished_at: None,
+                    progress: None,
+                    result: None,
+                    error: None,
+                },
+                abort: None,
+                cancel_token: None,
+            },
+        );
+        self.prune();
+        id
+    }
+
+    fn prune(&mut self) {
+        let finished: Vec<u64> = self
+            .jobs
+            .values()
+            .filter(|job| job.info.status != JobStatus::Running)
+            .map(|job| job.info.id)
+            .collect();
+        let excess = finished.len().saturating_sub(MAX_FINISHED_JOBS);
+        for id in finished.into_iter().take(excess) {
+            self.jobs.remove(&id);
+        }
+    }
+
+    /// 标记任务结束，已被取消的任务保持取消状态
+    fn finish(
+        &mut self,
+        id: u64,
+        result: Result<serde_json::Value, QueryError>,
+    ) -> Option<JobInfo> {
+        let job = self.jobs.get_mut(&id)?;
+        if job.info.status != JobStatus::Running {
+            return None;
+        }
+        job.info.finished_at = Some(Utc::now());
+        match result {
+            Ok(value) => {
+                job.info.status = JobStatus::Succeeded;
+                job.info.result = Some(value);
+            }
+            Err(e) => {
+                job.info.status = JobStatus::Failed;
+                job.info.error = Some(e);
+            }
+        }
+        job.abort = None;
+        job.cancel_token = None;
+        Some(job.info.summary())
+    }
+
+    pub fn list(&self) -> Vec<JobInfo> {
+        self.jobs.values().map(|job| job.info.summary()).collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<JobInfo> {
+        self.jobs.get(&id).map(|job| job.info.clone())
+    }
+}
+
+pub fn lock(jobs: &SharedJobs) -> std::sync::MutexGuard<'_, JobRegistry> {
+    // 任务线程 panic 不应让整个登记表不可用
+    jobs.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// 登记并在 tokio 上启动任务，立即返回任务 ID
+///
+/// 任务使用独立连接执行，不占用界面所用的连接，可以通过 cancel token 取消。
+pub fn start(app: AppHandle, jobs: SharedJobs, config: Config, request: JobRequest) -> u64 {
+    let id = lock(&jobs).insert(&request);
+
+    let task_jobs = jobs.clone();
+    let handle = tokio::spawn(async move {
+        let result = run(&app, &task_jobs, id, &config, request).await;
+        let summary = lock(&task_jobs).finish(id, result);
+        if let Some(summary) = summary {
+            if let Err(e) = app.emit(JOB_FINISHED_EVENT, summary) {
+                eprintln!("发送任务事件失败: {}", e);
+            }
+        }
+    });
+
+    // 任务可能在登记中止句柄之前就被取消，此时直接中止
+    if let Some(job) = lock(&jobs).jobs.get_mut(&id) {
+        if job.info.status == JobStatus::Running {
+            job.abort = Some(handle.abort_handle());
+        } else {
+            handle.abort();
+        }
+    }
+    id
+}
+
+async fn run(
+    app: &AppHandle,
+    jobs: &SharedJobs,
+    id: u64,
+    config: &Config,
+    request: JobRequest,
+) -> Result<serde_json::Value, QueryError> {
+    let (client, connection) = config
+        .connect(NoTls)
+        .await
+        .map_err(|e| QueryError::from_pg("连接失败", &e))?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            eprintln!("PostgreSQL连接错误: {}", e);
+        }
+    });
+
+    // 连接期间已被取消的任务不再执行
+    match lock(jobs).jobs.get_mut(&id) {
+        Some(job) if job.info.status == JobStatus::Running => {
+            job.cancel_token = Some(client.cancel_token());
+        }
+        _ => return Err("任务已取消".into()),
+    }
+
+    let report = |progress: serde_json::Value| {
+        if let Some(job) = lock(jobs).jobs.get_mut(&id) {
+            job.info.progress = Some(progress.clone());
+        }
+        if let Err(e) = app.emit(JOB_PROGRESS_EVENT, JobProgress { id, progress }) {
+            eprintln!("发送任务进度失败: {}", e);
+        }
+    };
+
+    execute(&client, request, report).await
+}
+
+async fn execute(
+    client: &Client,
+    request: JobRequest,
+    report: impl Fn(serde_json::Value),
+) -> Result<serde_json::Value, QueryError> {
+    // 任务连接用完即关闭，缓存只在本次任务内有效
+    let cache = StatementCache::default();
+    let to_value = |value: Result<serde_json::Value, serde_json::Error>| {
+        value.map_err(|e| QueryError::new(format!("序列化失败: {}", e)))
+    };
+
+    match request {
+        JobRequest::Query { query } => crate::run_query(client, &cache, &query).await,
+        JobRequest::Script {
+            sql,
+            continue_on_error,
+        } => {
+            let result = script::run_script(client, &cache, &sql, continue_on_error, |progress| {
+                report(serde_json::to_value(progress).unwrap_or_default())
+            })
+            .await?;
+            to_value(serde_json::to_value(result))
+        }
+        JobRequest::Import { options } => {
+            let summary = copy::copy_in_file(client, &options, |progress| {
+                report(serde_json::to_value(progress).unwrap_or_default())
+            })
+            .await?;
+            to_value(serde_json::to_value(summary))
+        }
+        JobRequest::Export { options } => {
+            let summary = copy::copy_out_file(client, &options, |progress| {
+                report(serde_json::to_value(progress).unwrap_or_default())
+            })
+            .await?;
+            to_value(serde_json::to_value(summary))
+        }
+    }
+}
+
+/// 取消正在运行的任务，返回任务是否处于运行状态
+pub async fn cancel(app: &AppHandle, jobs: &SharedJobs, id: u64) -> Result<bool, QueryError> {
+    let (abort, cancel_token, summary) = {
+        let mut registry = lock(jobs);
+        let job = match registry.jobs.get_mut(&id) {
+            Some(job) if job.info.status == JobStatus::Running => job,
+            Some(_) => return Ok(false),
+            None => return Err(format!("任务不存在: {}", id).into()),
+        };
+        job.info.status = JobStatus::Cancelled;
+        job.info.finished_at = Some(Utc::now());
+        (
+            job.abort.take(),
+            job.cancel_token.take(),
+            job.info.summary(),
+        )
+    };
+
+    // 先让服务器取消正在执行的语句，再中止本地任务
+    if let Some(token) = cancel_token {
+        if let Err(e) = token.cancel_query(NoTls).await {
+            eprintln!("取消查询失败: {}", e);
+        }
+    }
+    if let Some(abort) = abort {
+        abort.abort();
+    }
+
+    if let Err(e) = app.emit(JOB_FINISHED_EVENT, summary) {
+        eprintln!("发送任务事件失败: {}", e);
+    }
+    Ok(true)
+}
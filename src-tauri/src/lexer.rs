@@ -0,0 +1,528 @@
+//! SQL 词法分析
+//!
+//! 只做切分，不做语法分析：识别注释、字符串、美元引用、带引号的标识符等，
+//! 保证按分号拆分语句、替换变量等操作不会误伤字符串和函数体中的内容。
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Whitespace,
+    /// `-- ...` 行注释（不含换行符）
+    LineComment,
+    /// `/* ... */` 块注释，支持嵌套
+    BlockComment,
+    /// 关键字或未加引号的标识符
+    Word,
+    /// `"..."` 标识符
+    QuotedIdent,
+    /// `'...'`、`E'...'` 等字符串常量
+    String,
+    /// `$tag$ ... $tag$` 字符串
+    DollarString,
+    Number,
+    /// `$1` 位置参数
+    Param,
+    /// 运算符，如 `=`、`<>`、`::`、`->>`
+    Operator,
+    /// 其它单字符符号：`(` `)` `,` `.` `[` `]` 等
+    Punct,
+    Semicolon,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    /// 在原文中的字节偏移
+    pub offset: usize,
+}
+
+impl Token<'_> {
+    /// 空白和注释
+    pub fn is_trivia(&self) -> bool {
+        matches!(
+            self.kind,
+            TokenKind::Whitespace | TokenKind::LineComment | TokenKind::BlockComment
+        )
+    }
+}
+
+/// 拆分后的一条语句
+#[derive(Debug, Clone, Copy)]
+pub struct Statement<'a> {
+    /// 去掉首尾空白后的语句文本，不含结尾分号
+    pub text: &'a str,
+    /// 语句在原文中的字节偏移
+    pub offset: usize,
+}
+
+/// 常用 SQL 关键字（大写），用于格式化时转换大小写和自动补全
+pub const KEYWORDS: &[&str] = &[
+    "ADD",
+    "ALL",
+    "ALTER",
+    "AND",
+    "ANY",
+    "AS",
+    "ASC",
+    "BEGIN",
+    "BETWEEN",
+    "BY",
+    "CASCADE",
+    "CASE",
+    "CAST",
+    "CHECK",
+    "COLUMN",
+    "COMMIT",
+    "CONFLICT",
+    "CONSTRAINT",
+    "CREATE",
+    "CROSS",
+    "CURRENT",
+    "DEFAULT",
+    "DELETE",
+    "DESC",
+    "DISTINCT",
+    "DO",
+    "DROP",
+    "ELSE",
+    "END",
+    "EXCEPT",
+    "EXISTS",
+    "FALSE",
+    "FETCH",
+    "FILTER",
+    "FIRST",
+    "FOLLOWING",
+    "FOR",
+    "FOREIGN",
+    "FROM",
+    "FULL",
+    "FUNCTION",
+    "GRANT",
+    "GROUP",
+    "HAVING",
+    "IF",
+    "ILIKE",
+    "IN",
+    "INDEX",
+    "INNER",
+    "INSERT",
+    "INTERSECT",
+    "INTERVAL",
+    "INTO",
+    "IS",
+    "JOIN",
+    "KEY",
+    "LATERAL",
+    "LAST",
+    "LEFT",
+    "LIKE",
+    "LIMIT",
+    "LOCAL",
+    "MATERIALIZED",
+    "NATURAL",
+    "NEXT",
+    "NOT",
+    "NOTHING",
+    "NULL",
+    "NULLS",
+    "OFFSET",
+    "ON",
+    "ONLY",
+    "OR",
+    "ORDER",
+    "OUTER",
+    "OVER",
+    "PARTITION",
+    "PRECEDING",
+    "PRIMARY",
+    "RANGE",
+    "RECURSIVE",
+    "REFERENCES",
+    "REPLACE",
+    "RETURNING",
+    "RETURNS",
+    "REVOKE",
+    "RIGHT",
+    "ROLLBACK",
+    "ROW",
+    "ROWS",
+    "SCHEMA",
+    "SELECT",
+    "SET",
+    "SIMILAR",
+    "SOME",
+    "TABLE",
+    "TEMP",
+    "TEMPORARY",
+    "THEN",
+    "TO",
+    "TRIGGER",
+    "TRUE",
+    "TRUNCATE",
+    "UNBOUNDED",
+    "UNION",
+    "UNIQUE",
+    "UPDATE",
+    "USING",
+    "VALUES",
+    "VIEW",
+    "WHEN",
+    "WHERE",
+    "WINDOW",
+    "WITH",
+];
+
+const OPERATOR_CHARS: &str = "+-*/<>=~!@#%^&|`?:";
+
+fn peek(sql: &str, pos: usize) -> Option<char> {
+    sql.get(pos..).and_then(|s| s.chars().next())
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// 将 SQL 切分为词法单元，结果拼接起来与原文完全一致
+pub fn tokenize(sql: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while let Some(c) = peek(sql, pos) {
+        let start = pos;
+        let rest = &sql[pos..];
+
+        let kind = if c.is_whitespace() {
+            pos += rest
+                .find(|ch: char| !ch.is_whitespace())
+                .unwrap_or(rest.len());
+            TokenKind::Whitespace
+        } else if rest.starts_with("--") {
+            pos += rest.find('\n').unwrap_or(rest.len());
+            TokenKind::LineComment
+        } else if rest.starts_with("/*") {
+            pos += block_comment_len(rest);
+            TokenKind::BlockComment
+        } else if c == '\'' {
+            pos += quoted_len(rest, '\'', false);
+            TokenKind::String
+        } else if c == '"' {
+            pos += quoted_len(rest, '"', false);
+            TokenKind::QuotedIdent
+        } else if c == '$' {
+            if let Some(len) = dollar_string_len(rest) {
+                pos += len;
+                TokenKind::DollarString
+            } else if rest[1..].starts_with(|ch: char| ch.is_ascii_digit()) {
+                pos += 1 + rest[1..]
+                    .find(|ch: char| !ch.is_ascii_digit())
+                    .unwrap_or(rest.len() - 1);
+                TokenKind::Param
+            } else {
+                pos += 1;
+                TokenKind::Punct
+            }
+        } else if c.is_ascii_digit()
+            || (c == '.' && rest[1..].starts_with(|ch: char| ch.is_ascii_digit()))
+        {
+            pos += number_len(rest);
+            TokenKind::Number
+        } else if is_ident_start(c) {
+            let len = rest
+                .find(|ch: char| !is_ident_char(ch))
+                .unwrap_or(rest.len());
+            let word = &rest[..len];
+            let unicode_quote = if len == 1 && word.eq_ignore_ascii_case("u") {
+                rest[1..]
+                    .strip_prefix('&')
+                    .and_then(|r| r.chars().next())
+                    .filter(|q| *q == '\'' || *q == '"')
+            } else {
+                None
+            };
+            if let Some(quote) = unicode_quote {
+                // U&'...'、U&"..." Unicode 转义字符串和标识符，可带 UESCAPE 子句
+                let literal = 2 + quoted_len(&rest[2..], quote, false);
+                pos += literal + uescape_len(&rest[literal..]);
+                if quote == '\'' {
+                    TokenKind::String
+                } else {
+                    TokenKind::QuotedIdent
+                }
+            } else if len == 1 && "eEbBxXnN".contains(word) && rest[1..].starts_with('\'') {
+                // E'...'、B'...'、X'...'、N'...' 前缀字符串
+                let escapes = word.eq_ignore_ascii_case("e");
+                pos += 1 + quoted_len(&rest[1..], '\'', escapes);
+                TokenKind::String
+            } else {
+                pos += len;
+                TokenKind::Word
+            }
+        } else if c == ';' {
+            pos += 1;
+            TokenKind::Semicolon
+        } else if OPERATOR_CHARS.contains(c) {
+            pos += operator_len(rest);
+            TokenKind::Operator
+        } else {
+            pos += c.len_utf8();
+            TokenKind::Punct
+        };
+
+        tokens.push(Token {
+            kind,
+            text: &sql[start..pos],
+            offset: start,
+        });
+    }
+
+    tokens
+}
+
+fn block_comment_len(rest: &str) -> usize {
+    let bytes = rest.as_bytes();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i..].starts_with(b"/*") {
+            depth += 1;
+            i += 2;
+        } else if bytes[i..].starts_with(b"*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    // 未闭合的注释延伸到末尾
+    bytes.len()
+}
+
+/// 引号包围的内容长度（含引号），连续两个引号视为转义；
+/// `backslash` 为 true 时同时支持反斜杠转义（E'...' 字符串）
+fn quoted_len(rest: &str, quote: char, backslash: bool) -> usize {
+    let bytes = rest.as_bytes();
+    let quote = quote as u8;
+    let mut i = 1;
+    while i < bytes.len() {
+        if backslash && bytes[i] == b'\\' {
+            i += 2;
+        } else if bytes[i] == quote {
+            if bytes.get(i + 1) == Some(&quote) {
+                i += 2;
+            } else {
+                return i + 1;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+/// U&'...' 之后 ` UESCAPE '!'` 子句的长度，没有时为 0
+fn uescape_len(rest: &str) -> usize {
+    let keyword = rest.len() - rest.trim_start().len();
+    let after_keyword = keyword + "UESCAPE".len();
+    match rest.get(keyword..after_keyword) {
+        Some(word) if word.eq_ignore_ascii_case("UESCAPE") => {}
+        _ => return 0,
+    }
+    // UESCAPE 后面必须是单独的词，且跟着一个字符串
+    let tail = &rest[after_keyword..];
+    let quote = tail.len() - tail.trim_start().len();
+    if !tail[quote..].starts_with('\'') {
+        return 0;
+    }
+    after_keyword + quote + quoted_len(&tail[quote..], '\'', false)
+}
+
+/// `$tag$...$tag$` 的长度，开头不是合法的美元引用时返回 None
+fn dollar_string_len(rest: &str) -> Option<usize> {
+    let tag_end = rest[1..].find('$')? + 1;
+    let tag = &rest[1..tag_end];
+    let valid_tag = tag.is_empty()
+        || (tag.starts_with(is_ident_start)
+            && tag.chars().all(|c| c.is_alphanumeric() || c == '_'));
+    if !valid_tag {
+        return None;
+    }
+
+    let delimiter = &rest[..=tag_end];
+    let body_start = delimiter.len();
+    match rest[body_start..].find(delimiter) {
+        Some(end) => Some(body_start + end + delimiter.len()),
+        None => Some(rest.len()),
+    }
+}
+
+fn number_len(rest: &str) -> usize {
+    let bytes = rest.as_bytes();
+    let mut i = 0;
+    let mut seen_dot = false;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'0'..=b'9' | b'_' => i += 1,
+            // `1..2` 之类的范围写法中点号不属于数字
+            b'.' if !seen_dot && bytes.get(i + 1) != Some(&b'.') => {
+                seen_dot = true;
+                i += 1;
+            }
+            b'e' | b'E' => {
+                let mut j = i + 1;
+                if matches!(bytes.get(j), Some(b'+') | Some(b'-')) {
+                    j += 1;
+                }
+                if bytes.get(j).is_some_and(|b| b.is_ascii_digit()) {
+                    i = j;
+                    seen_dot = true;
+                } else {
+                    break;
+                }
+            }
+            _ => break,
+        }
+    }
+    i
+}
+
+fn operator_len(rest: &str) -> usize {
+    let mut len = 0;
+    for (i, c) in rest.char_indices() {
+        if !OPERATOR_CHARS.contains(c) {
+            break;
+        }
+        // 运算符中出现注释起始符时在此截断
+        if i > 0 && (rest[i..].starts_with("--") || rest[i..].starts_with("/*")) {
+            break;
+        }
+        len = i + c.len_utf8();
+    }
+    len
+}
+
+/// 按分号拆分多条语句，忽略字符串、注释和函数体中的分号
+///
+/// 只包含空白或注释的片段会被丢弃。
+pub fn split_statements(sql: &str) -> Vec<Statement<'_>> {
+    let mut statements = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut end = 0;
+
+    let mut flush = |start: &mut Option<usize>, end: usize| {
+        if let Some(begin) = start.take() {
+            statements.push(Statement {
+                text: &sql[begin..end],
+                offset: begin,
+            });
+        }
+    };
+
+    for token in tokenize(sql) {
+        match token.kind {
+            TokenKind::Semicolon => flush(&mut start, end),
+            _ if token.is_trivia() => {}
+            _ => {
+                // 语句从第一个有效词法单元开始，前导注释不计入
+                start.get_or_insert(token.offset);
+                end = token.offset + token.text.len();
+            }
+        }
+    }
+    flush(&mut start, end);
+
+    statements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 去掉空白后的 (类型, 文本)
+    fn tokens(sql: &str) -> Vec<(TokenKind, &str)> {
+        tokenize(sql)
+            .into_iter()
+            .filter(|t| t.kind != TokenKind::Whitespace)
+            .map(|t| (t.kind, t.text))
+            .collect()
+    }
+
+    #[test]
+    fn tokens_cover_the_input() {
+        let sql = "SELECT a.b, 'it''s', E'\\n' FROM \"T\" WHERE x >= $1 -- end";
+        let text: String = tokenize(sql).iter().map(|t| t.text).collect();
+        assert_eq!(text, sql);
+    }
+
+    #[test]
+    fn dollar_quoted_bodies() {
+        assert_eq!(
+            tokens("SELECT $$a; 'b'$$, $fn$ $$ ; $fn$, $1"),
+            [
+                (TokenKind::Word, "SELECT"),
+                (TokenKind::DollarString, "$$a; 'b'$$"),
+                (TokenKind::Punct, ","),
+                (TokenKind::DollarString, "$fn$ $$ ; $fn$"),
+                (TokenKind::Punct, ","),
+                (TokenKind::Param, "$1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unicode_escape_literals() {
+        assert_eq!(
+            tokens("SELECT U&'d\\0061t', u&'d!0061t' UESCAPE '!', U&\"d\\0061t\""),
+            [
+                (TokenKind::Word, "SELECT"),
+                (TokenKind::String, "U&'d\\0061t'"),
+                (TokenKind::Punct, ","),
+                (TokenKind::String, "u&'d!0061t' UESCAPE '!'"),
+                (TokenKind::Punct, ","),
+                (TokenKind::QuotedIdent, "U&\"d\\0061t\""),
+            ]
+        );
+        // 不是 U& 前缀时 & 是运算符
+        assert_eq!(
+            tokens("u & 'x'"),
+            [
+                (TokenKind::Word, "u"),
+                (TokenKind::Operator, "&"),
+                (TokenKind::String, "'x'"),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_block_comments() {
+        assert_eq!(
+            tokens("/* a /* b */ c */ x"),
+            [
+                (TokenKind::BlockComment, "/* a /* b */ c */"),
+                (TokenKind::Word, "x"),
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_statements_outside_strings_and_bodies() {
+        let sql = "-- lead\nSELECT ';';\n\nCREATE FUNCTION f() RETURNS int AS $$ SELECT 1; $$ LANGUAGE sql;\n;  ";
+        let statements = split_statements(sql);
+        let texts: Vec<&str> = statements.iter().map(|s| s.text).collect();
+        assert_eq!(
+            texts,
+            [
+                "SELECT ';'",
+                "CREATE FUNCTION f() RETURNS int AS $$ SELECT 1; $$ LANGUAGE sql",
+            ]
+        );
+        assert_eq!(statements[0].offset, sql.find("SELECT").unwrap());
+    }
+}
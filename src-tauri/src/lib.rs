@@ -1,15 +1,51 @@
-use serde::{Deserialize, Serialize};
+mod completion;
+mod copy;
+mod dsl;
+mod error;
+mod formatter;
+mod history;
+mod ident;
+mod jobs;
+mod lexer;
+mod notify;
+mod params;
+mod script;
+mod simple;
+mod stmt_cache;
+mod validate;
+mod variables;
+
+use error::QueryError;
+use history::{HistoryEntry, HistoryFilter, HistoryStatus, QueryHistory};
+use serde::Deserialize;
+use std::collections::BTreeSet;
 use std::sync::Arc;
-use tauri::State;
+use std::time::Instant;
+use stmt_cache::StatementCache;
+use tauri::{AppHandle, Emitter, Manager, State};
 use tokio::sync::Mutex;
-use tokio_postgres::{types::Type, Client, Config, NoTls, Row};
+use tokio_postgres::types::{ToSql, Type};
+use tokio_postgres::{Client, Config, NoTls, Row};
 
 struct PostgreSQLConnection {
     client: Option<Client>,
+    // 连接标识（user@host:port）和数据库名，用于记录查询历史
+    label: String,
+    database: String,
+    // 当前 LISTEN 的通道
+    channels: BTreeSet<String>,
+    // 连接配置，后台任务用它建立独立连接
+    config: Option<Config>,
+    // 预编译语句缓存，随连接切换清空
+    statements: StatementCache,
+    // 自动补全用的数据库目录，首次补全时加载，执行 DDL 后清空
+    catalog: Option<completion::Catalog>,
 }
 
 struct AppState {
     connection: Arc<Mutex<PostgreSQLConnection>>,
+    history: Arc<Mutex<QueryHistory>>,
+    jobs: jobs::SharedJobs,
 }
 
 #[derive(Deserialize)]
@@ -21,11 +57,6 @@ struct ConnectConfig {
     database: String,
 }
 
-#[derive(Serialize)]
-struct QueryResult {
-    data: Vec<serde_json::Value>,
-    total: Option<i64>,
-}
 // 查询库名
 #[tauri::command]
 async fn get_database_name(state: State<'_, AppState>) -> Result<String, String> {
@@ -107,6 +138,7 @@ async fn get_database_name(state: State<'_, AppState>) -> Result<String, String>
 #[tauri::command]
 async fn connect_postgresql(
     config: ConnectConfig,
+    app: AppHandle,
     state: State<'_, AppState>,
 ) -> Result<bool, String> {
     let mut app_connection = state.connection.lock().await;
@@ -125,12 +157,8 @@ async fn connect_postgresql(
         .await
         .map_err(|e| format!("连接失败: {}", e))?;
 
-    // 后台运行连接
-    tokio::spawn(async move {
-        if let Err(e) = connection.await {
-            eprintln!("PostgreSQL连接错误: {}", e);
-        }
-    });
+    // 后台运行连接，同时转发 LISTEN 收到的通知
+    tokio::spawn(notify::drive_connection(connection, app));
 
     // 测试连接
     client
@@ -141,6 +169,12 @@ async fn connect_postgresql(
     println!("PostgreSQL连接成功! 数据库: {}", config.database);
 
     app_connection.client = Some(client);
+    app_connection.config = Some(pg_config);
+    app_connection.statements.clear();
+    app_connection.catalog = None;
+    app_connection.label = format!("{}@{}:{}", config.username, config.host, config.port);
+    app_connection.database = config.database;
+    app_connection.channels.clear();
 
     Ok(true)
 }
@@ -149,6 +183,68 @@ async fn connect_postgresql(
 async fn disconnect_postgresql(state: State<'_, AppState>) -> Result<(), String> {
     let mut app_connection = state.connection.lock().await;
     app_connection.client = None;
+    app_connection.config = None;
+    app_connection.statements.clear();
+    app_connection.catalog = None;
+    app_connection.channels.clear();
+    Ok(())
+}
+
+// 订阅通知通道，收到的消息以 pg-notification 事件发送到前端
+#[tauri::command]
+async fn listen(channel: String, state: State<'_, AppState>) -> Result<Vec<String>, QueryError> {
+    let mut app_connection = state.connection.lock().await;
+    let client = app_connection.client.as_ref().ok_or("未连接到数据库")?;
+
+    client
+        .batch_execute(&format!("LISTEN {}", ident::quote_ident(&channel)))
+        .await
+        .map_err(|e| QueryError::from_pg("订阅失败", &e))?;
+
+    app_connection.channels.insert(channel);
+    Ok(app_connection.channels.iter().cloned().collect())
+}
+
+// 取消订阅，channel 为 * 时取消全部
+#[tauri::command]
+async fn unlisten(channel: String, state: State<'_, AppState>) -> Result<Vec<String>, QueryError> {
+    let mut app_connection = state.connection.lock().await;
+    let client = app_connection.client.as_ref().ok_or("未连接到数据库")?;
+
+    let sql = if channel == "*" {
+        "UNLISTEN *".to_string()
+    } else {
+        format!("UNLISTEN {}", ident::quote_ident(&channel))
+    };
+    client
+        .batch_execute(&sql)
+        .await
+        .map_err(|e| QueryError::from_pg("取消订阅失败", &e))?;
+
+    if channel == "*" {
+        app_connection.channels.clear();
+    } else {
+        app_connection.channels.remove(&channel);
+    }
+    Ok(app_connection.channels.iter().cloned().collect())
+}
+
+#[tauri::command]
+async fn notify(
+    channel: String,
+    payload: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<(), QueryError> {
+    let app_connection = state.connection.lock().await;
+    let client = app_connection.client.as_ref().ok_or("未连接到数据库")?;
+
+    client
+        .execute(
+            "SELECT pg_notify($1, $2)",
+            &[&channel, &payload.unwrap_or_default()],
+        )
+        .await
+        .map_err(|e| QueryError::from_pg("发送通知失败", &e))?;
     Ok(())
 }
 
@@ -183,14 +279,237 @@ async fn list_databases(state: State<'_, AppState>) -> Result<String, String> {
 async fn execute_query(
     query: serde_json::Value,
     state: State<'_, AppState>,
-) -> Result<String, String> {
-    let connection = state.connection.lock().await;
+) -> Result<String, QueryError> {
+    let mut connection = state.connection.lock().await;
     let client = connection.client.as_ref().ok_or("未连接到数据库")?;
 
+    let started = Instant::now();
+    let result = run_query(client, &connection.statements, &query).await;
+    let duration_ms = started.elapsed().as_millis() as u64;
+
+    let sql = match query.get("sql").and_then(|v| v.as_str()) {
+        Some(sql) => {
+            if completion::changes_schema(sql) {
+                connection.catalog = None;
+            }
+            sql.to_string()
+        }
+        None => query.to_string(),
+    };
+    let source = (connection.label.clone(), connection.database.clone());
+    // 先释放连接，写历史文件不阻塞其它查询
+    drop(connection);
+
+    // 记录查询历史，写入失败不影响查询结果
+    record_history(
+        &state,
+        source,
+        sql,
+        duration_ms,
+        result.as_ref().ok().and_then(history::result_row_count),
+        result.as_ref().err().map(|e| e.to_string()),
+    )
+    .await;
+
+    let value = result?;
+    Ok(serde_json::to_string(&value).map_err(|e| format!("序列化失败: {}", e))?)
+}
+
+/// 记录一条查询历史，`source` 为 (连接标识, 数据库)
+async fn record_history(
+    state: &AppState,
+    source: (String, String),
+    sql: String,
+    duration_ms: u64,
+    row_count: Option<u64>,
+    error: Option<String>,
+) {
+    let (connection, database) = source;
+    let entry = HistoryEntry {
+        id: 0,
+        connection,
+        database,
+        sql,
+        duration_ms,
+        row_count,
+        status: if error.is_none() {
+            HistoryStatus::Success
+        } else {
+            HistoryStatus::Error
+        },
+        error,
+        timestamp: chrono::Utc::now(),
+        pinned: false,
+    };
+    if let Err(e) = state.history.lock().await.record(entry).await {
+        eprintln!("记录查询历史失败: {}", e);
+    }
+}
+
+async fn run_query(
+    client: &Client,
+    cache: &StatementCache,
+    query: &serde_json::Value,
+) -> Result<serde_json::Value, QueryError> {
+    // timeout_ms / lock_timeout_ms 用 SET LOCAL 设置，只在本次查询所在的事务中有效，
+    // 不会留在共享连接的会话上
+    let timeout_ms = timeout_option(query, "timeout_ms")?;
+    let lock_timeout_ms = timeout_option(query, "lock_timeout_ms")?;
+    if timeout_ms.is_none() && lock_timeout_ms.is_none() {
+        return dispatch_query(client, cache, query).await;
+    }
+    if let Some(statement) = query
+        .get("sql")
+        .and_then(|v| v.as_str())
+        .and_then(transaction_unsafe)
+    {
+        return Err(format!(
+            "设置了超时的查询在事务中执行，不能包含 {} 语句，请去掉 timeout_ms / lock_timeout_ms 后执行",
+            statement
+        )
+        .into());
+    }
+
+    let mut setup = String::new();
+    if let Some(ms) = timeout_ms {
+        setup.push_str(&format!("SET LOCAL statement_timeout = {};", ms));
+    }
+    if let Some(ms) = lock_timeout_ms {
+        setup.push_str(&format!("SET LOCAL lock_timeout = {};", ms));
+    }
+
+    // 连接上已有打开的事务时 SAVEPOINT 成功，否则报 25P01
+    let in_transaction = match client.batch_execute("SAVEPOINT run_query").await {
+        Ok(()) => true,
+        Err(e) if e.code().map(|c| c.code()) == Some("25P01") => false,
+        Err(e) => return Err(QueryError::from_pg("设置超时失败", &e)),
+    };
+
+    let result = if in_transaction {
+        // 在调用方的事务中：SET LOCAL 到事务结束为止，执行后恢复原值；
+        // 恢复失败（如事务已中止）时设置也会随事务结束失效
+        let previous = client
+            .query_one(
+                "SELECT current_setting('statement_timeout'), current_setting('lock_timeout')",
+                &[],
+            )
+            .await
+            .map_err(|e| QueryError::from_pg("读取超时设置失败", &e))?;
+        let previous_timeout: String = previous.get(0);
+        let previous_lock_timeout: String = previous.get(1);
+        client
+            .batch_execute(&format!("RELEASE SAVEPOINT run_query;{}", setup))
+            .await
+            .map_err(|e| QueryError::from_pg("设置超时失败", &e))?;
+
+        let result = dispatch_query(client, cache, query).await;
+        if let Err(e) = client
+            .execute(
+                "SELECT set_config('statement_timeout', $1, true), set_config('lock_timeout', $2, true)",
+                &[&previous_timeout, &previous_lock_timeout],
+            )
+            .await
+        {
+            eprintln!("恢复超时设置失败: {}", e);
+        }
+        result
+    } else {
+        // 不在事务中：包在隐式事务里执行，成功提交，失败回滚
+        client
+            .batch_execute(&format!("BEGIN;{}", setup))
+            .await
+            .map_err(|e| QueryError::from_pg("设置超时失败", &e))?;
+        match dispatch_query(client, cache, query).await {
+            Ok(value) => client
+                .batch_execute("COMMIT")
+                .await
+                .map(|()| value)
+                .map_err(|e| QueryError::from_pg("提交失败", &e)),
+            Err(e) => {
+                if let Err(rollback) = client.batch_execute("ROLLBACK").await {
+                    eprintln!("回滚失败: {}", rollback);
+                }
+                Err(e)
+            }
+        }
+    };
+
+    result.map_err(|mut e| {
+        // 57014 也可能是手动取消，只有设置了对应超时才标记
+        let timeout = match e.code.as_deref() {
+            Some("57014") if timeout_ms.is_some() => Some(error::TimeoutKind::Statement),
+            Some("55P03") if lock_timeout_ms.is_some() => Some(error::TimeoutKind::Lock),
+            _ => None,
+        };
+        if let Some(details) = e.details.as_mut() {
+            details.timeout = timeout;
+        }
+        e
+    })
+}
+
+/// SQL 中第一条不能放进事务执行的语句：事务控制语句，
+/// 以及 VACUUM、CREATE DATABASE、CREATE INDEX CONCURRENTLY 等不能在事务块中执行的语句
+fn transaction_unsafe(sql: &str) -> Option<String> {
+    lexer::split_statements(sql)
+        .into_iter()
+        .find_map(|statement| {
+            let words: Vec<String> = lexer::tokenize(statement.text)
+                .into_iter()
+                .filter(|t| t.kind == lexer::TokenKind::Word)
+                .take(4)
+                .map(|t| t.text.to_uppercase())
+                .collect();
+            let first = words.first().map(String::as_str).unwrap_or("");
+            let has = |word: &str| words.iter().any(|w| w == word);
+            let unsafe_statement = match first {
+                "BEGIN" | "START" | "COMMIT" | "END" | "ROLLBACK" | "ABORT" | "SAVEPOINT"
+                | "RELEASE" | "VACUUM" | "DISCARD" => true,
+                "PREPARE" => has("TRANSACTION"),
+                "CREATE" | "DROP" | "REINDEX" => {
+                    has("DATABASE") || has("TABLESPACE") || has("CONCURRENTLY")
+                }
+                "ALTER" => has("SYSTEM"),
+                _ => false,
+            };
+            unsafe_statement.then(|| words.iter().take(2).cloned().collect::<Vec<_>>().join(" "))
+        })
+}
+
+// 读取超时参数（毫秒），必须是非负整数，0 表示不限制
+fn timeout_option(query: &serde_json::Value, key: &str) -> Result<Option<u64>, QueryError> {
+    match query.get(key) {
+        None | Some(serde_json::Value::Null) => Ok(None),
+        Some(value) => value
+            .as_u64()
+            .map(Some)
+            .ok_or_else(|| format!("{} 必须是非负整数（毫秒）", key).into()),
+    }
+}
+
+async fn dispatch_query(
+    client: &Client,
+    cache: &StatementCache,
+    query: &serde_json::Value,
+) -> Result<serde_json::Value, QueryError> {
     // 检查查询类型
     if let Some(sql_str) = query.get("sql").and_then(|v| v.as_str()) {
-        // 支持直接SQL查询
-        return execute_sql(client, sql_str).await;
+        // 支持直接SQL查询，variables 为 :name / ${name} 变量的取值
+        let empty = serde_json::Map::new();
+        let variables = query
+            .get("variables")
+            .and_then(|v| v.as_object())
+            .unwrap_or(&empty);
+
+        // mode 为 simple 时整段 SQL 一次往返执行，返回全部结果集（值为文本）
+        if query.get("mode").and_then(|v| v.as_str()) == Some("simple") {
+            if !variables::list_variables(sql_str).is_empty() {
+                return Err("simple 模式不支持变量".into());
+            }
+            return simple::execute_simple(client, sql_str).await;
+        }
+
+        return execute_sql(client, cache, sql_str, variables).await;
     }
 
     // 原有的JSON格式查询
@@ -199,83 +518,363 @@ async fn execute_query(
         .and_then(|v| v.as_str())
         .ok_or("查询必须包含 table 字段或 sql 字段")?;
 
+    // schema 可以单独指定，也可以写在 table 中（schema.table）
+    let schema = query.get("schema").and_then(|v| v.as_str());
+
     let operation = query
         .get("operation")
         .and_then(|v| v.as_str())
         .ok_or("查询必须包含 operation 字段")?;
 
-    // 克隆query以避免所有权问题
-    execute_query_internal(&client, operation, table, query.clone()).await
+    execute_query_internal(client, cache, operation, schema, table, query).await
 }
 
-// 执行原始SQL查询
-async fn execute_sql(client: &Client, sql: &str) -> Result<String, String> {
-    // 支持多条SQL语句，按分号分割
-    let statements: Vec<&str> = sql
-        .split(';')
-        .map(|s| s.trim())
-        .filter(|s| !s.is_empty())
-        .collect();
+// 脚本模式：逐条执行并返回每条语句的结果，可选择失败后继续
+#[tauri::command]
+async fn execute_script(
+    sql: String,
+    continue_on_error: Option<bool>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<String, QueryError> {
+    let mut connection = state.connection.lock().await;
+    let client = connection.client.as_ref().ok_or("未连接到数据库")?;
 
-    if statements.is_empty() {
-        return Err("没有有效的SQL语句".to_string());
+    let result = script::run_script(
+        client,
+        &connection.statements,
+        &sql,
+        continue_on_error.unwrap_or(false),
+        |progress| {
+            if let Err(e) = app.emit(script::SCRIPT_PROGRESS_EVENT, progress) {
+                eprintln!("发送脚本进度失败: {}", e);
+            }
+        },
+    )
+    .await?;
+    if completion::changes_schema(&sql) {
+        connection.catalog = None;
     }
 
-    let mut all_results = Vec::new();
+    let row_count = result
+        .statements
+        .iter()
+        .filter_map(|s| s.result.as_ref().and_then(|v| v.get("rows_affected")))
+        .filter_map(|v| v.as_u64())
+        .sum();
+    let error = (result.failed > 0).then(|| format!("{} 条语句执行失败", result.failed));
+    let source = (connection.label.clone(), connection.database.clone());
+    drop(connection);
+    record_history(
+        &state,
+        source,
+        sql,
+        result.duration_ms,
+        Some(row_count),
+        error,
+    )
+    .await;
+
+    Ok(serde_json::to_string(&result).map_err(|e| format!("序列化失败: {}", e))?)
+}
 
-    for statement in statements {
-        // 判断SQL类型
-        let upper_stmt = statement.to_uppercase();
+// 将本地 CSV/文本/二进制文件流式导入表
+#[tauri::command]
+async fn copy_from_file(
+    options: copy::CopyOptions,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<String, QueryError> {
+    let connection = state.connection.lock().await;
+    let client = connection.client.as_ref().ok_or("未连接到数据库")?;
 
-        if upper_stmt.starts_with("SELECT") {
-            // 查询操作
-            let rows = client
-                .query(statement, &[])
-                .await
-                .map_err(|e| format!("查询失败: {}", e))?;
-
-            let data = rows_to_json(&rows);
-            all_results.push(serde_json::json!({
-                "type": "select",
-                "sql": statement,
-                "data": data,
-                "rows_affected": data.len()
-            }));
-        } else if upper_stmt.starts_with("INSERT")
-            || upper_stmt.starts_with("UPDATE")
-            || upper_stmt.starts_with("DELETE")
-        {
-            // 写操作
-            let result = client
-                .execute(statement, &[])
-                .await
-                .map_err(|e| format!("执行失败: {}", e))?;
+    let summary = copy::copy_in_file(client, &options, |progress| {
+        if let Err(e) = app.emit(copy::COPY_PROGRESS_EVENT, progress) {
+            eprintln!("发送COPY进度失败: {}", e);
+        }
+    })
+    .await?;
 
-            all_results.push(serde_json::json!({
-                "type": "write",
-                "sql": statement,
-                "rows_affected": result
-            }));
-        } else {
-            // 其他操作（CREATE, ALTER, DROP等）
-            let result = client
-                .execute(statement, &[])
-                .await
-                .map_err(|e| format!("执行失败: {}", e))?;
+    Ok(serde_json::to_string(&summary).map_err(|e| format!("序列化失败: {}", e))?)
+}
+
+// 将表或查询结果流式导出到本地文件
+#[tauri::command]
+async fn copy_to_file(
+    options: copy::CopyOptions,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<String, QueryError> {
+    let connection = state.connection.lock().await;
+    let client = connection.client.as_ref().ok_or("未连接到数据库")?;
 
-            all_results.push(serde_json::json!({
-                "type": "ddl",
-                "sql": statement,
-                "rows_affected": result
-            }));
+    let summary = copy::copy_out_file(client, &options, |progress| {
+        if let Err(e) = app.emit(copy::COPY_PROGRESS_EVENT, progress) {
+            eprintln!("发送COPY进度失败: {}", e);
         }
+    })
+    .await?;
+
+    Ok(serde_json::to_string(&summary).map_err(|e| format!("序列化失败: {}", e))?)
+}
+
+// 在后台启动长时间运行的操作，立即返回任务 ID
+#[tauri::command]
+async fn start_job(
+    request: jobs::JobRequest,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<u64, QueryError> {
+    let connection = state.connection.lock().await;
+    let config = connection.config.clone().ok_or("未连接到数据库")?;
+
+    Ok(jobs::start(app, state.jobs.clone(), config, request))
+}
+
+#[tauri::command]
+async fn list_jobs(state: State<'_, AppState>) -> Result<String, String> {
+    let jobs = jobs::lock(&state.jobs).list();
+
+    let result = serde_json::json!({
+        "jobs": jobs
+    });
+
+    Ok(result.to_string())
+}
+
+#[tauri::command]
+async fn job_status(id: u64, state: State<'_, AppState>) -> Result<String, String> {
+    let job = jobs::lock(&state.jobs)
+        .get(id)
+        .ok_or_else(|| format!("任务不存在: {}", id))?;
+
+    serde_json::to_string(&job).map_err(|e| format!("序列化失败: {}", e))
+}
+
+#[tauri::command]
+async fn cancel_job(
+    id: u64,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<bool, QueryError> {
+    jobs::cancel(&app, &state.jobs, id).await
+}
+
+// 自动补全，cursor 为光标的字符偏移
+#[tauri::command]
+async fn complete_sql(
+    text: String,
+    cursor: usize,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let mut connection = state.connection.lock().await;
+    let client = connection.client.as_ref().ok_or("未连接到数据库")?;
+
+    if connection.catalog.is_none() {
+        let catalog = completion::Catalog::load(client)
+            .await
+            .map_err(|e| format!("读取数据库目录失败: {}", e))?;
+        connection.catalog = Some(catalog);
+    }
+    let catalog = connection.catalog.as_ref().ok_or("读取数据库目录失败")?;
+
+    let completions = completion::complete(catalog, &text, cursor);
+    serde_json::to_string(&completions).map_err(|e| format!("序列化失败: {}", e))
+}
+
+// 清空补全目录缓存，下次补全时重新读取
+#[tauri::command]
+async fn refresh_catalog(state: State<'_, AppState>) -> Result<(), String> {
+    state.connection.lock().await.catalog = None;
+    Ok(())
+}
+
+// 检查SQL：在回滚的事务中逐条预编译，报告错误、参数类型和结果列，不执行查询
+#[tauri::command]
+async fn validate_sql(
+    sql: String,
+    variables: Option<serde_json::Map<String, serde_json::Value>>,
+    state: State<'_, AppState>,
+) -> Result<String, QueryError> {
+    let connection = state.connection.lock().await;
+    let client = connection.client.as_ref().ok_or("未连接到数据库")?;
+
+    let result = validate::validate(client, &sql, &variables.unwrap_or_default()).await?;
+    Ok(serde_json::to_string(&result).map_err(|e| format!("序列化失败: {}", e))?)
+}
+
+// 格式化SQL，options 缺省时使用两个空格缩进、关键字大写
+#[tauri::command]
+async fn format_sql(
+    sql: String,
+    options: Option<formatter::FormatOptions>,
+) -> Result<String, String> {
+    Ok(formatter::format_sql(&sql, &options.unwrap_or_default()))
+}
+
+#[tauri::command]
+async fn statement_cache_stats(state: State<'_, AppState>) -> Result<String, String> {
+    let connection = state.connection.lock().await;
+    let stats = connection.statements.stats();
+
+    serde_json::to_string(&stats).map_err(|e| format!("序列化失败: {}", e))
+}
+
+#[tauri::command]
+async fn clear_statement_cache(state: State<'_, AppState>) -> Result<(), String> {
+    let connection = state.connection.lock().await;
+    connection.statements.clear();
+    Ok(())
+}
+
+#[tauri::command]
+async fn search_history(
+    filter: Option<HistoryFilter>,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let history = state.history.lock().await;
+    let entries = history.search(&filter.unwrap_or_default());
+
+    let result = serde_json::json!({
+        "entries": entries
+    });
+
+    Ok(result.to_string())
+}
+
+#[tauri::command]
+async fn pin_history(id: u64, pinned: bool, state: State<'_, AppState>) -> Result<bool, String> {
+    let mut history = state.history.lock().await;
+    history.set_pinned(id, pinned).await
+}
+
+#[tauri::command]
+async fn purge_history(
+    before: Option<chrono::DateTime<chrono::Utc>>,
+    keep_latest: Option<usize>,
+    state: State<'_, AppState>,
+) -> Result<usize, String> {
+    let mut history = state.history.lock().await;
+    history.purge(before, keep_latest).await
+}
+
+// 执行原始SQL查询
+async fn execute_sql(
+    client: &Client,
+    cache: &StatementCache,
+    sql: &str,
+    variables: &serde_json::Map<String, serde_json::Value>,
+) -> Result<serde_json::Value, QueryError> {
+    // 支持多条SQL语句，按分号分割（字符串、注释和函数体中的分号不算）
+    let statements = lexer::split_statements(sql);
+
+    if statements.is_empty() {
+        return Err("没有有效的SQL语句".into());
+    }
+
+    // 有变量未提供取值时不执行，返回变量列表供前端填写
+    let missing = variables::missing_variables(sql, variables);
+    if !missing.is_empty() {
+        return Ok(serde_json::json!({
+            "type": "variables",
+            "variables": missing
+        }));
+    }
+
+    let mut all_results = Vec::new();
+
+    for statement in statements {
+        // 参数编号按语句独立计算
+        let bound = variables::bind_variables(statement.text, variables)?;
+        let bound_statement = lexer::Statement {
+            text: &bound.sql,
+            offset: statement.offset,
+        };
+        let params = params::as_refs(&bound.params);
+        let result = execute_statement(client, cache, sql, bound_statement, &params)
+            .await
+            // 错误位置是替换变量后的语句中的位置，换算回原文后重新定位
+            .map_err(|e| {
+                e.map_position(|p| bound.original_position(p))
+                    .locate(sql, statement.offset)
+            })?;
+        all_results.push(result);
     }
 
     // 如果只有一条结果，直接返回；否则返回数组
     if all_results.len() == 1 {
-        Ok(serde_json::to_string(&all_results[0]).map_err(|e| format!("序列化失败: {}", e))?)
+        Ok(all_results.swap_remove(0))
+    } else {
+        Ok(serde_json::Value::Array(all_results))
+    }
+}
+
+// 执行单条语句，`sql` 为语句所在的完整文本，用于把错误位置换算成行列
+async fn execute_statement(
+    client: &Client,
+    cache: &StatementCache,
+    sql: &str,
+    statement: lexer::Statement<'_>,
+    params: &[&(dyn ToSql + Sync)],
+) -> Result<serde_json::Value, QueryError> {
+    let offset = statement.offset;
+    let statement = statement.text;
+
+    // 判断SQL类型
+    let upper_stmt = statement.to_uppercase();
+
+    // COPY 的 STDIN/STDOUT 需要走专门的流式接口
+    if upper_stmt.starts_with("COPY")
+        && (upper_stmt.contains("STDIN") || upper_stmt.contains("STDOUT"))
+    {
+        return Err(QueryError::new(
+            "COPY ... FROM STDIN / TO STDOUT 请使用 copy_from_file / copy_to_file 执行",
+        )
+        .locate(sql, offset));
+    }
+
+    // 查询和写操作经过预编译语句缓存，DDL 等其它语句直接执行
+    if upper_stmt.starts_with("SELECT") {
+        // 查询操作
+        let rows = cache
+            .query(client, statement, params)
+            .await
+            .map_err(|e| QueryError::from_pg("查询失败", &e).locate(sql, offset))?;
+
+        let data = rows_to_json(&rows);
+        Ok(serde_json::json!({
+            "type": "select",
+            "sql": statement,
+            "data": data,
+            "rows_affected": data.len()
+        }))
+    } else if upper_stmt.starts_with("INSERT")
+        || upper_stmt.starts_with("UPDATE")
+        || upper_stmt.starts_with("DELETE")
+    {
+        // 写操作
+        let result = cache
+            .execute(client, statement, params)
+            .await
+            .map_err(|e| QueryError::from_pg("执行失败", &e).locate(sql, offset))?;
+
+        Ok(serde_json::json!({
+            "type": "write",
+            "sql": statement,
+            "rows_affected": result
+        }))
     } else {
-        Ok(serde_json::to_string(&all_results).map_err(|e| format!("序列化失败: {}", e))?)
+        // 其他操作（CREATE, ALTER, DROP等）
+        let result = client
+            .execute(statement, params)
+            .await
+            .map_err(|e| QueryError::from_pg("执行失败", &e).locate(sql, offset))?;
+
+        Ok(serde_json::json!({
+            "type": "ddl",
+            "sql": statement,
+            "rows_affected": result
+        }))
     }
 }
 
@@ -293,20 +892,10 @@ async fn list_collections(database: String, state: State<'_, AppState>) -> Resul
 
     // 如果数据库与当前连接的数据库相同，直接使用当前连接
     if current_db == database {
-        let rows = current_client
-            .query(
-                "SELECT table_name FROM information_schema.tables WHERE table_schema = 'public' AND table_type = 'BASE TABLE'",
-                &[]
-            )
+        // 包括所有 schema 中的表、视图和物化视图
+        let collections = dsl::relation_names(current_client)
             .await
-            .map_err(|e| format!("获取表列表失败: {}", e))?;
-
-        // 使用try_get避免panic
-        let collections: Vec<String> = rows
-            .iter()
-            .filter_map(|row| row.try_get("table_name").unwrap_or(None))
-            .collect();
-        println!("collections: {:#?}", collections);
+            .map_err(|e| e.to_string())?;
         let result = serde_json::json!({
             "collections": collections
         });
@@ -368,133 +957,28 @@ async fn list_collections(database: String, state: State<'_, AppState>) -> Resul
 
 async fn execute_query_internal(
     client: &Client,
+    cache: &StatementCache,
     operation: &str,
+    schema: Option<&str>,
     table: &str,
-    query: serde_json::Value,
-) -> Result<String, String> {
-    match operation {
-        "find" => {
-            let filter = query
-                .get("filter")
-                .and_then(|v| v.as_object())
-                .cloned()
-                .unwrap_or_else(|| serde_json::Map::new());
-            let where_clause = build_where_clause(&filter);
-            let sql = format!("SELECT * FROM {}{}", table, where_clause);
-
-            let rows = client
-                .query(&sql, &[])
-                .await
-                .map_err(|e| format!("查询失败: {}", e))?;
+    query: &serde_json::Value,
+) -> Result<serde_json::Value, QueryError> {
+    // 表名先经目录校验，生成的 SQL 只使用引用后的名称
+    let relation = dsl::resolve_relation(client, cache, schema, table).await?;
 
-            let data = rows_to_json(&rows);
-            let result = QueryResult { data, total: None };
-            Ok(serde_json::to_string(&result).map_err(|e| format!("序列化失败: {}", e))?)
-        }
-        "findOne" => {
-            let filter = query
-                .get("filter")
-                .and_then(|v| v.as_object())
-                .cloned()
-                .unwrap_or_else(|| serde_json::Map::new());
-            let where_clause = build_where_clause(&filter);
-            let sql = format!("SELECT * FROM {}{} LIMIT 1", table, where_clause);
-
-            let row = client
-                .query_opt(&sql, &[])
-                .await
-                .map_err(|e| format!("查询失败: {}", e))?;
-
-            let data = match row {
-                Some(row) => vec![row_to_json(&row)],
-                None => vec![],
-            };
-
-            let result = QueryResult { data, total: None };
-            Ok(serde_json::to_string(&result).map_err(|e| format!("序列化失败: {}", e))?)
-        }
-        "count" => {
-            let filter = query
-                .get("filter")
-                .and_then(|v| v.as_object())
-                .cloned()
-                .unwrap_or_else(|| serde_json::Map::new());
-            let where_clause = build_where_clause(&filter);
-            let sql = format!("SELECT COUNT(*) as count FROM {}{}", table, where_clause);
-
-            let row = client
-                .query_one(&sql, &[])
-                .await
-                .map_err(|e| format!("计数失败: {}", e))?;
-
-            let count: i64 = row.get("count");
-            let result = QueryResult {
-                data: vec![],
-                total: Some(count),
-            };
-            Ok(serde_json::to_string(&result).map_err(|e| format!("序列化失败: {}", e))?)
-        }
-        _ => Err(format!("不支持的操作: {}", operation)),
-    }
-}
-
-fn build_where_clause(filter: &serde_json::Map<String, serde_json::Value>) -> String {
-    if filter.is_empty() {
-        return String::new();
-    }
-
-    let conditions: Vec<String> = filter
-        .iter()
-        .map(|(key, value)| {
-            match value {
-                serde_json::Value::String(s) => format!("{} = '{}'", key, s),
-                serde_json::Value::Number(n) => format!("{} = {}", key, n),
-                serde_json::Value::Bool(b) => format!("{} = {}", key, b),
-                serde_json::Value::Object(obj) => {
-                    // 处理操作符，如 $gt, $lt 等
-                    if let Some(gt) = obj.get("$gt") {
-                        return format!("{} > {}", key, json_value_to_sql(gt));
-                    }
-                    if let Some(gte) = obj.get("$gte") {
-                        return format!("{} >= {}", key, json_value_to_sql(gte));
-                    }
-                    if let Some(lt) = obj.get("$lt") {
-                        return format!("{} < {}", key, json_value_to_sql(lt));
-                    }
-                    if let Some(lte) = obj.get("$lte") {
-                        return format!("{} <= {}", key, json_value_to_sql(lte));
-                    }
-                    if let Some(ne) = obj.get("$ne") {
-                        return format!("{} != {}", key, json_value_to_sql(ne));
-                    }
-                    if let Some(_in) = obj.get("$in") {
-                        // 处理 $in 操作符
-                        if let Some(arr) = _in.as_array() {
-                            let values: Vec<String> =
-                                arr.iter().map(|v| json_value_to_sql(v)).collect();
-                            return format!("{} IN ({})", key, values.join(", "));
-                        }
-                    }
-                    format!("{} = '{}'", key, value)
-                }
-                _ => format!("{} = '{}'", key, value),
-            }
-        })
-        .collect();
-
-    if conditions.is_empty() {
-        String::new()
-    } else {
-        format!(" WHERE {}", conditions.join(" AND "))
-    }
-}
-
-fn json_value_to_sql(value: &serde_json::Value) -> String {
-    match value {
-        serde_json::Value::String(s) => format!("'{}'", s),
-        serde_json::Value::Number(n) => n.to_string(),
-        serde_json::Value::Bool(b) => b.to_string(),
-        _ => format!("'{}'", value),
+    match operation {
+        "find" => dsl::find(client, cache, &relation, query).await,
+        "findOne" => dsl::find_one(client, cache, &relation, query).await,
+        "count" => dsl::count(client, cache, &relation, query).await,
+        "distinct" => dsl::distinct(client, cache, &relation, query).await,
+        "insertOne" => dsl::insert_one(client, cache, &relation, query).await,
+        "insertMany" => dsl::insert_many(client, cache, &relation, query).await,
+        "updateOne" => dsl::update_one(client, cache, &relation, query).await,
+        "updateMany" => dsl::update_many(client, cache, &relation, query).await,
+        "deleteOne" => dsl::delete_one(client, cache, &relation, query).await,
+        "deleteMany" => dsl::delete_many(client, cache, &relation, query).await,
+        "aggregate" => dsl::aggregate(client, cache, &relation, query).await,
+        _ => Err(format!("不支持的操作: {}", operation).into()),
     }
 }
 
@@ -672,16 +1156,49 @@ fn row_to_json(row: &Row) -> serde_json::Value {
 pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
-        .manage(AppState {
-            connection: Arc::new(Mutex::new(PostgreSQLConnection { client: None })),
+        .setup(|app| {
+            let data_dir = app.path().app_data_dir()?;
+            app.manage(AppState {
+                connection: Arc::new(Mutex::new(PostgreSQLConnection {
+                    client: None,
+                    label: String::new(),
+                    database: String::new(),
+                    channels: BTreeSet::new(),
+                    config: None,
+                    statements: StatementCache::default(),
+                    catalog: None,
+                })),
+                history: Arc::new(Mutex::new(QueryHistory::load(data_dir))),
+                jobs: jobs::SharedJobs::default(),
+            });
+            Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             connect_postgresql,
             disconnect_postgresql,
+            listen,
+            unlisten,
+            notify,
             list_databases,
             list_collections,
             execute_query,
-            get_database_name
+            execute_script,
+            copy_from_file,
+            copy_to_file,
+            start_job,
+            list_jobs,
+            job_status,
+            cancel_job,
+            statement_cache_stats,
+            clear_statement_cache,
+            validate_sql,
+            format_sql,
+            complete_sql,
+            refresh_catalog,
+            get_database_name,
+            search_history,
+            pin_history,
+            purge_history
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
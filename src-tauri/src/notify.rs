@@ -0,0 +1,54 @@
+use futures_util::{stream, StreamExt};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_postgres::{AsyncMessage, Connection};
+
+/// NOTIFY 消息转发到前端时使用的事件名
+pub const NOTIFICATION_EVENT: &str = "pg-notification";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NotificationPayload {
+    pub channel: String,
+    pub payload: String,
+    /// 发送通知的后端进程 ID
+    pub process_id: i32,
+}
+
+/// 驱动连接直到关闭，并把收到的 NOTIFY 消息作为 Tauri 事件转发
+///
+/// 取代直接 `connection.await`：后者会丢弃所有异步消息。
+pub async fn drive_connection<S, T>(mut connection: Connection<S, T>, app: AppHandle)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+
+    while let Some(message) = messages.next().await {
+        match message {
+            Ok(AsyncMessage::Notification(notification)) => {
+                let payload = NotificationPayload {
+                    channel: notification.channel().to_string(),
+                    payload: notification.payload().to_string(),
+                    process_id: notification.process_id(),
+                };
+                if let Err(e) = app.emit(NOTIFICATION_EVENT, payload) {
+                    eprintln!("转发通知失败: {}", e);
+                }
+            }
+            Ok(AsyncMessage::Notice(notice)) => {
+                println!(
+                    "PostgreSQL提示: {}: {}",
+                    notice.severity(),
+                    notice.message()
+                );
+            }
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("PostgreSQL连接错误: {}", e);
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! JSON 值绑定为查询参数
+//!
+//! 参数统一以文本格式发送，由服务器按推断出的参数类型解析，
+//! 效果等同于写字面量，但不存在拼接 SQL 带来的转义和注入问题。
+
+use bytes::{BufMut, BytesMut};
+use std::error::Error;
+use tokio_postgres::types::{to_sql_checked, Format, IsNull, Kind, ToSql, Type};
+
+#[derive(Debug, Clone)]
+pub struct JsonParam(pub serde_json::Value);
+
+impl ToSql for JsonParam {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        match to_text(&self.0, ty) {
+            Some(text) => {
+                out.put_slice(text.as_bytes());
+                Ok(IsNull::No)
+            }
+            None => Ok(IsNull::Yes),
+        }
+    }
+
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+
+    fn encode_format(&self, _ty: &Type) -> Format {
+        Format::Text
+    }
+
+    to_sql_checked!();
+}
+
+/// JSON 值在指定类型下的文本表示，null 返回 None
+fn to_text(value: &serde_json::Value, ty: &Type) -> Option<String> {
+    match value {
+        serde_json::Value::Null => None,
+        // json/jsonb 参数按 JSON 文本解析，字符串也要带引号
+        _ if *ty == Type::JSON || *ty == Type::JSONB => Some(value.to_string()),
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Bool(b) => Some(b.to_string()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::Array(items) if matches!(ty.kind(), Kind::Array(_)) => {
+            Some(array_literal(items))
+        }
+        // 其它类型使用 JSON 文本
+        _ => Some(value.to_string()),
+    }
+}
+
+/// 构造数组字面量，如 `{1,"a b",NULL}`
+fn array_literal(items: &[serde_json::Value]) -> String {
+    let elements: Vec<String> = items
+        .iter()
+        .map(|item| match item {
+            serde_json::Value::Null => "NULL".to_string(),
+            serde_json::Value::Bool(b) => b.to_string(),
+            serde_json::Value::Number(n) => n.to_string(),
+            serde_json::Value::Array(nested) => array_literal(nested),
+            serde_json::Value::String(s) => quote_array_element(s),
+            serde_json::Value::Object(_) => quote_array_element(&item.to_string()),
+        })
+        .collect();
+    format!("{{{}}}", elements.join(","))
+}
+
+fn quote_array_element(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// 转换为 `client.query` 需要的参数切片
+pub fn as_refs(params: &[JsonParam]) -> Vec<&(dyn ToSql + Sync)> {
+    params.iter().map(|p| p as &(dyn ToSql + Sync)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn text_by_type() {
+        assert_eq!(to_text(&json!("a b"), &Type::TEXT).as_deref(), Some("a b"));
+        assert_eq!(to_text(&json!(1.5), &Type::NUMERIC).as_deref(), Some("1.5"));
+        assert_eq!(to_text(&json!(null), &Type::INT4), None);
+        assert_eq!(
+            to_text(&json!([1, null, "x \"y\""]), &Type::TEXT_ARRAY).as_deref(),
+            Some("{1,NULL,\"x \\\"y\\\"\"}")
+        );
+    }
+
+    #[test]
+    fn json_strings_keep_quotes() {
+        assert_eq!(
+            to_text(&json!("vip"), &Type::JSONB).as_deref(),
+            Some("\"vip\"")
+        );
+        assert_eq!(
+            to_text(&json!("vip"), &Type::JSON).as_deref(),
+            Some("\"vip\"")
+        );
+        assert_eq!(
+            to_text(&json!([1, 2]), &Type::JSONB).as_deref(),
+            Some("[1,2]")
+        );
+        assert_eq!(
+            to_text(&json!({"a": 1}), &Type::JSONB).as_deref(),
+            Some("{\"a\":1}")
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use crate::error::QueryError;
+use crate::lexer;
+use crate::stmt_cache::StatementCache;
+use serde::Serialize;
+use std::time::Instant;
+use tokio_postgres::Client;
+
+/// 脚本执行进度事件名，每条语句执行结束后发送一次
+pub const SCRIPT_PROGRESS_EVENT: &str = "script-progress";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatementStatus {
+    Success,
+    Error,
+    /// 前面的语句失败且未开启 continue_on_error，未执行
+    Skipped,
+}
+
+#[derive(Serialize)]
+pub struct StatementResult {
+    pub index: usize,
+    pub sql: String,
+    /// 语句起始行号（从 1 开始）
+    pub line: usize,
+    pub status: StatementStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<QueryError>,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScriptProgress {
+    pub index: usize,
+    pub total: usize,
+    pub status: StatementStatus,
+    pub duration_ms: u64,
+    pub rows_affected: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct ScriptResult {
+    pub statements: Vec<StatementResult>,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub duration_ms: u64,
+}
+
+/// 逐条执行脚本，收集每条语句的结果或错误
+///
+/// `continue_on_error` 为 false 时，第一条失败之后的语句标记为 skipped。
+/// 每条语句结束后调用 `on_progress`。
+pub async fn run_script(
+    client: &Client,
+    cache: &StatementCache,
+    sql: &str,
+    continue_on_error: bool,
+    mut on_progress: impl FnMut(ScriptProgress),
+) -> Result<ScriptResult, QueryError> {
+    let statements = lexer::split_statements(sql);
+    if statements.is_empty() {
+        return Err("没有有效的SQL语句".into());
+    }
+
+    let started = Instant::now();
+    let total = statements.len();
+    let mut results = Vec::with_capacity(total);
+    let mut failed = false;
+
+    for (index, statement) in statements.into_iter().enumerate() {
+        let line = sql[..statement.offset].matches('\n').count() + 1;
+
+        if failed && !continue_on_error {
+            results.push(StatementResult {
+                index,
+                sql: statement.text.to_string(),
+                line,
+                status: StatementStatus::Skipped,
+                result: None,
+                error: None,
+                duration_ms: 0,
+            });
+            on_progress(ScriptProgress {
+                index,
+                total,
+                status: StatementStatus::Skipped,
+                duration_ms: 0,
+                rows_affected: None,
+            });
+            continue;
+        }
+
+        let statement_started = Instant::now();
+        let outcome = crate::execute_statement(client, cache, sql, statement, &[]).await;
+        let duration_ms = statement_started.elapsed().as_millis() as u64;
+
+        let (status, result, error) = match outcome {
+            Ok(value) => (StatementStatus::Success, Some(value), None),
+            Err(e) => {
+                failed = true;
+                (StatementStatus::Error, None, Some(e))
+            }
+        };
+        let rows_affected = result
+            .as_ref()
+            .and_then(|v| v.get("rows_affected"))
+            .and_then(|v| v.as_u64());
+
+        on_progress(ScriptProgress {
+            index,
+            total,
+            status,
+            duration_ms,
+            rows_affected,
+        });
+        results.push(StatementResult {
+            index,
+            sql: statement.text.to_string(),
+            line,
+            status,
+            result,
+            error,
+            duration_ms,
+        });
+    }
+
+    let count = |status| results.iter().filter(|r| r.status == status).count();
+    Ok(ScriptResult {
+        succeeded: count(StatementStatus::Success),
+        failed: count(StatementStatus::Error),
+        skipped: count(StatementStatus::Skipped),
+        duration_ms: started.elapsed().as_millis() as u64,
+        statements: results,
+    })
+}
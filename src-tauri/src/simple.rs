@@ -0,0 +1,104 @@
+//! 基于简单查询协议的执行模式
+//!
+//! 整段 SQL 一次发送到服务器，适合无法预编译的语句或需要一次往返执行的脚本。
+//! 返回每条语句的结果集和命令标签，值均为文本。
+
+use crate::error::QueryError;
+use crate::lexer::{self, TokenKind};
+use tokio_postgres::{Client, SimpleQueryMessage};
+
+/// 依次执行整段 SQL，按语句顺序返回结果；只有一条结果时直接返回该结果
+pub async fn execute_simple(client: &Client, sql: &str) -> Result<serde_json::Value, QueryError> {
+    let statements = lexer::split_statements(sql);
+    if statements.is_empty() {
+        return Err("没有有效的SQL语句".into());
+    }
+
+    let messages = client
+        .simple_query(sql)
+        .await
+        .map_err(|e| QueryError::from_pg("执行失败", &e).locate(sql, 0))?;
+
+    let mut results = Vec::new();
+    let mut columns: Option<Vec<String>> = None;
+    let mut data = Vec::new();
+
+    for message in messages {
+        match message {
+            SimpleQueryMessage::RowDescription(description) => {
+                columns = Some(description.iter().map(|c| c.name().to_string()).collect());
+            }
+            SimpleQueryMessage::Row(row) => {
+                let mut map = serde_json::Map::new();
+                for (i, column) in row.columns().iter().enumerate() {
+                    let value = match row.get(i) {
+                        Some(v) => serde_json::Value::String(v.to_string()),
+                        None => serde_json::Value::Null,
+                    };
+                    map.insert(column.name().to_string(), value);
+                }
+                data.push(serde_json::Value::Object(map));
+            }
+            SimpleQueryMessage::CommandComplete(rows_affected) => {
+                // 每条语句结束时服务器发送一次 CommandComplete，按顺序对应拆分出的语句
+                let statement = statements.get(results.len()).map(|s| s.text).unwrap_or("");
+                let mut result = serde_json::json!({
+                    "type": if columns.is_some() { "select" } else { "command" },
+                    "sql": statement,
+                    "command": command_tag(statement),
+                    "rows_affected": rows_affected
+                });
+                if let Some(columns) = columns.take() {
+                    result["columns"] = serde_json::json!(columns);
+                    result["data"] = serde_json::Value::Array(std::mem::take(&mut data));
+                }
+                results.push(result);
+            }
+            _ => {}
+        }
+    }
+
+    if results.len() == 1 {
+        Ok(results.swap_remove(0))
+    } else {
+        Ok(serde_json::Value::Array(results))
+    }
+}
+
+/// 根据语句开头的关键字推断命令标签，如 `SELECT`、`CREATE TABLE`
+///
+/// tokio_postgres 只返回影响的行数，不返回服务器的命令标签原文。
+fn command_tag(statement: &str) -> String {
+    let words: Vec<String> = lexer::tokenize(statement)
+        .into_iter()
+        .filter(|t| !t.is_trivia())
+        .take_while(|t| t.kind == TokenKind::Word)
+        .map(|t| t.text.to_uppercase())
+        .take(4)
+        .collect();
+
+    match words.first().map(String::as_str) {
+        Some("CREATE") | Some("ALTER") | Some("DROP") => {
+            // 跳过 OR REPLACE、UNIQUE、TEMP 等修饰词，取对象类型
+            let object = words[1..].iter().find(|w| {
+                !matches!(
+                    w.as_str(),
+                    "OR" | "REPLACE"
+                        | "UNIQUE"
+                        | "TEMP"
+                        | "TEMPORARY"
+                        | "UNLOGGED"
+                        | "MATERIALIZED"
+                )
+            });
+            let materialized = words.iter().any(|w| w == "MATERIALIZED");
+            match object {
+                Some(object) if materialized => format!("{} MATERIALIZED {}", words[0], object),
+                Some(object) => format!("{} {}", words[0], object),
+                None => words[0].clone(),
+            }
+        }
+        Some(first) => first.to_string(),
+        None => String::new(),
+    }
+}
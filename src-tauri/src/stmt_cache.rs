@@ -0,0 +1,201 @@
+//! 每个连接的预编译语句缓存
+//!
+//! 以 SQL 文本为键缓存 `Statement`，容量有限，超出时淘汰最久未使用的语句。
+//! 表结构变化导致缓存的语句失效时，丢弃缓存并重新预编译一次；
+//! 如果错误已使所在的事务中止，重试不会成功，返回原始错误。
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{Client, Error, Row, Statement};
+
+/// 默认缓存的语句数
+pub const DEFAULT_CAPACITY: usize = 100;
+
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct CacheStats {
+    pub capacity: usize,
+    pub size: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub invalidations: u64,
+    /// 命中率，尚无请求时为 0
+    pub hit_rate: f64,
+}
+
+struct CachedStatement {
+    statement: Statement,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<String, CachedStatement>,
+    tick: u64,
+    stats: CacheStats,
+}
+
+/// 使用同步锁，临界区内没有 await，可以在持有连接锁时通过共享引用访问
+pub struct StatementCache {
+    capacity: usize,
+    inner: Mutex<Inner>,
+}
+
+impl Default for StatementCache {
+    fn default() -> Self {
+        StatementCache::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl StatementCache {
+    pub fn new(capacity: usize) -> Self {
+        StatementCache {
+            capacity: capacity.max(1),
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lookup(&self, sql: &str) -> Option<Statement> {
+        let mut inner = self.lock();
+        inner.tick += 1;
+        let tick = inner.tick;
+        match inner.entries.get_mut(sql) {
+            Some(entry) => {
+                entry.last_used = tick;
+                let statement = entry.statement.clone();
+                inner.stats.hits += 1;
+                Some(statement)
+            }
+            None => {
+                inner.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn store(&self, sql: &str, statement: Statement) {
+        let mut inner = self.lock();
+        if inner.entries.len() >= self.capacity && !inner.entries.contains_key(sql) {
+            let oldest = inner
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                inner.entries.remove(&oldest);
+                inner.stats.evictions += 1;
+            }
+        }
+        let last_used = inner.tick;
+        inner.entries.insert(
+            sql.to_string(),
+            CachedStatement {
+                statement,
+                last_used,
+            },
+        );
+    }
+
+    fn invalidate(&self, sql: &str) {
+        let mut inner = self.lock();
+        if inner.entries.remove(sql).is_some() {
+            inner.stats.invalidations += 1;
+        }
+    }
+
+    /// 清空缓存，统计数据一并重置
+    pub fn clear(&self) {
+        *self.lock() = Inner::default();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let inner = self.lock();
+        let requests = inner.stats.hits + inner.stats.misses;
+        CacheStats {
+            capacity: self.capacity,
+            size: inner.entries.len(),
+            hit_rate: if requests == 0 {
+                0.0
+            } else {
+                inner.stats.hits as f64 / requests as f64
+            },
+            ..inner.stats
+        }
+    }
+
+    /// 取缓存的语句，没有则预编译并放入缓存；返回值的第二项表示是否命中
+    async fn prepare(&self, client: &Client, sql: &str) -> Result<(Statement, bool), Error> {
+        if let Some(statement) = self.lookup(sql) {
+            return Ok((statement, true));
+        }
+        let statement = client.prepare(sql).await?;
+        self.store(sql, statement.clone());
+        Ok((statement, false))
+    }
+
+    pub async fn query(
+        &self,
+        client: &Client,
+        sql: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, Error> {
+        let (statement, cached) = self.prepare(client, sql).await?;
+        match client.query(&statement, params).await {
+            Err(e) if cached && is_schema_change(&e) => {
+                self.invalidate(sql);
+                let retried = match self.prepare(client, sql).await {
+                    Ok((statement, _)) => client.query(&statement, params).await,
+                    Err(retry) => Err(retry),
+                };
+                retried.map_err(|retry| original_if_aborted(e, retry))
+            }
+            result => result,
+        }
+    }
+
+    pub async fn execute(
+        &self,
+        client: &Client,
+        sql: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<u64, Error> {
+        let (statement, cached) = self.prepare(client, sql).await?;
+        match client.execute(&statement, params).await {
+            Err(e) if cached && is_schema_change(&e) => {
+                self.invalidate(sql);
+                let retried = match self.prepare(client, sql).await {
+                    Ok((statement, _)) => client.execute(&statement, params).await,
+                    Err(retry) => Err(retry),
+                };
+                retried.map_err(|retry| original_if_aborted(e, retry))
+            }
+            result => result,
+        }
+    }
+}
+
+/// 失败的语句发生在事务中时，事务已经中止，重试只会得到 25P02，此时返回原始错误
+fn original_if_aborted(original: Error, retry: Error) -> Error {
+    if retry.code().map(|c| c.code()) == Some("25P02") {
+        original
+    } else {
+        retry
+    }
+}
+
+/// 表结构变化导致缓存语句不可用的错误
+///
+/// 0A000：cached plan must not change result type；
+/// 42P01 / 42703 / 42704 / 42883：对象被删除后重建，旧语句引用的 OID 已失效。
+fn is_schema_change(err: &Error) -> bool {
+    matches!(
+        err.code().map(|c| c.code()),
+        Some("0A000") | Some("42P01") | Some("42703") | Some("42704") | Some("42883")
+    )
+}
@@ -0,0 +1,289 @@
+//! 只检查、不执行 SQL
+//!
+//! 在事务中逐条预编译语句，报告语法错误、不存在的表和列，以及参数类型和结果列。
+//! CREATE / ALTER / DROP 等结构变更会在事务中实际执行，使后续语句能看到新建的表，
+//! 最后整个事务回滚，数据库不受影响。执行结构变更会像真正执行时一样获取锁，
+//! 直到检查结束才释放。
+//!
+//! 连接上已有打开的事务时，检查在保存点中进行，结束后回滚到保存点，该事务保持原样；
+//! 该事务已中止时拒绝检查。
+
+use crate::error::QueryError;
+use crate::{lexer, variables};
+use serde::Serialize;
+use tokio_postgres::Client;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Valid,
+    Error,
+    /// 事务控制语句，不检查
+    Skipped,
+    /// 引用的对象不存在，但可能由前面未能执行的语句创建，无法确定是否有错
+    Warning,
+}
+
+#[derive(Serialize)]
+pub struct ColumnInfo {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub data_type: String,
+}
+
+#[derive(Serialize)]
+pub struct StatementCheck {
+    pub index: usize,
+    pub sql: String,
+    /// 语句起始行号（从 1 开始）
+    pub line: usize,
+    pub status: CheckStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<QueryError>,
+    /// 参数（`$n` 或值变量）推断出的类型
+    pub params: Vec<String>,
+    /// 结果列，非查询语句为空
+    pub columns: Vec<ColumnInfo>,
+    /// 结构变更在回滚的事务中执行过
+    pub applied: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ValidationResult {
+    /// 没有错误和警告
+    pub valid: bool,
+    pub statements: Vec<StatementCheck>,
+}
+
+/// 语句的处理方式
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// 事务控制，不检查
+    Transaction,
+    /// 不能在事务中执行，只检查语法
+    SyntaxOnly,
+    /// 结构变更，预编译后在事务中执行
+    Schema,
+    /// 其它语句只预编译
+    Prepare,
+}
+
+fn classify(statement: &str) -> Kind {
+    let words: Vec<String> = lexer::tokenize(statement)
+        .into_iter()
+        .filter(|t| !t.is_trivia())
+        .take(4)
+        .map(|t| t.text.to_uppercase())
+        .collect();
+    let first = words.first().map(String::as_str).unwrap_or("");
+    let has = |word: &str| words.iter().any(|w| w == word);
+
+    match first {
+        "BEGIN" | "START" | "COMMIT" | "END" | "ROLLBACK" | "ABORT" | "SAVEPOINT" | "RELEASE" => {
+            Kind::Transaction
+        }
+        "VACUUM" => Kind::SyntaxOnly,
+        "CREATE" | "DROP" | "REINDEX"
+            if has("DATABASE") || has("TABLESPACE") || has("CONCURRENTLY") =>
+        {
+            Kind::SyntaxOnly
+        }
+        "ALTER" if has("SYSTEM") || has("DATABASE") => Kind::SyntaxOnly,
+        "CREATE" | "ALTER" | "DROP" | "COMMENT" => Kind::Schema,
+        _ => Kind::Prepare,
+    }
+}
+
+/// 引用的对象不存在：42P01 表、42703 列、42704 类型等、42883 函数、3F000 schema
+fn is_missing_object(error: &QueryError) -> bool {
+    matches!(
+        error.code.as_deref(),
+        Some("42P01") | Some("42703") | Some("42704") | Some("42883") | Some("3F000")
+    )
+}
+
+/// 检查整段 SQL，`variables` 中没有取值的值变量按未知类型的参数处理
+pub async fn validate(
+    client: &Client,
+    sql: &str,
+    variables: &serde_json::Map<String, serde_json::Value>,
+) -> Result<ValidationResult, QueryError> {
+    let statements = lexer::split_statements(sql);
+    if statements.is_empty() {
+        return Err("没有有效的SQL语句".into());
+    }
+
+    // 不在事务中时 SAVEPOINT 报 25P01，自己开启事务，最后整个回滚；
+    // 在调用方的事务中时只回滚到保存点
+    let in_transaction = match client.batch_execute("SAVEPOINT validate_sql").await {
+        Ok(()) => true,
+        Err(e) if e.code().map(|c| c.code()) == Some("25P01") => {
+            client
+                .batch_execute("BEGIN")
+                .await
+                .map_err(|e| QueryError::from_pg("开始事务失败", &e))?;
+            false
+        }
+        Err(e) if e.code().map(|c| c.code()) == Some("25P02") => {
+            return Err("当前事务已中止，请先回滚后再检查".into());
+        }
+        Err(e) => return Err(QueryError::from_pg("检查失败", &e)),
+    };
+
+    let mut checks = Vec::with_capacity(statements.len());
+    let mut not_applied = false;
+    for (index, statement) in statements.into_iter().enumerate() {
+        let kind = classify(statement.text);
+        match check_statement(client, sql, index, statement, kind, variables, not_applied).await {
+            Ok(check) => {
+                // 没有执行的结构变更，后续语句可能依赖它创建的对象
+                not_applied |= matches!(kind, Kind::Schema | Kind::SyntaxOnly) && !check.applied;
+                checks.push(check);
+            }
+            Err(e) => {
+                finish(client, in_transaction).await;
+                return Err(e);
+            }
+        }
+    }
+
+    finish(client, in_transaction).await;
+
+    Ok(ValidationResult {
+        valid: checks
+            .iter()
+            .all(|c| matches!(c.status, CheckStatus::Valid | CheckStatus::Skipped)),
+        statements: checks,
+    })
+}
+
+/// 撤销检查中执行的结构变更
+async fn finish(client: &Client, in_transaction: bool) {
+    let rollback = if in_transaction {
+        "ROLLBACK TO SAVEPOINT validate_sql; RELEASE SAVEPOINT validate_sql"
+    } else {
+        "ROLLBACK"
+    };
+    if let Err(e) = client.batch_execute(rollback).await {
+        eprintln!("回滚失败: {}", e);
+    }
+}
+
+/// 检查一条语句；返回 Err 表示事务本身出错，无法继续
+///
+/// `not_applied` 表示前面有未能执行的结构变更。
+async fn check_statement(
+    client: &Client,
+    sql: &str,
+    index: usize,
+    statement: lexer::Statement<'_>,
+    kind: Kind,
+    values: &serde_json::Map<String, serde_json::Value>,
+    not_applied: bool,
+) -> Result<StatementCheck, QueryError> {
+    let mut check = StatementCheck {
+        index,
+        sql: statement.text.to_string(),
+        line: sql[..statement.offset].matches('\n').count() + 1,
+        status: CheckStatus::Valid,
+        error: None,
+        params: Vec::new(),
+        columns: Vec::new(),
+        applied: false,
+        note: None,
+    };
+
+    if kind == Kind::Transaction {
+        check.status = CheckStatus::Skipped;
+        check.note = Some("事务控制语句不检查".to_string());
+        return Ok(check);
+    }
+
+    // 未提供取值的值变量用 null 占位，只为得到 `$n` 形式的语句
+    let mut values = values.clone();
+    for variable in variables::list_variables(statement.text) {
+        if !variable.identifier {
+            values
+                .entry(variable.name)
+                .or_insert(serde_json::Value::Null);
+        }
+    }
+    let bound = match variables::bind_variables(statement.text, &values) {
+        Ok(bound) => bound,
+        Err(e) => {
+            check.status = CheckStatus::Error;
+            check.error = Some(e);
+            return Ok(check);
+        }
+    };
+
+    client
+        .batch_execute("SAVEPOINT validate_statement")
+        .await
+        .map_err(|e| QueryError::from_pg("创建保存点失败", &e))?;
+
+    let outcome = match client.prepare(&bound.sql).await {
+        Ok(prepared) => {
+            check.params = prepared
+                .params()
+                .iter()
+                .map(|t| t.name().to_string())
+                .collect();
+            check.columns = prepared
+                .columns()
+                .iter()
+                .map(|c| ColumnInfo {
+                    name: c.name().to_string(),
+                    data_type: c.type_().name().to_string(),
+                })
+                .collect();
+            // 带参数的结构变更无法直接执行，只做预编译
+            match kind {
+                Kind::Schema if bound.params.is_empty() => {
+                    client.batch_execute(&bound.sql).await.map(|_| true)
+                }
+                _ => Ok(false),
+            }
+        }
+        Err(e) => Err(e),
+    };
+
+    match outcome {
+        Ok(applied) => {
+            check.applied = applied;
+            check.note = match kind {
+                Kind::SyntaxOnly => Some("该语句不能在事务中执行，只检查了语法".to_string()),
+                Kind::Schema if !applied => {
+                    Some("带变量的结构变更没有执行，只检查了语法".to_string())
+                }
+                _ => None,
+            };
+            client
+                .batch_execute("RELEASE SAVEPOINT validate_statement")
+                .await
+                .map_err(|e| QueryError::from_pg("释放保存点失败", &e))?;
+        }
+        Err(e) => {
+            let error = QueryError::from_pg("检查失败", &e)
+                .map_position(|p| bound.original_position(p))
+                .locate(sql, statement.offset);
+            if not_applied && is_missing_object(&error) {
+                check.status = CheckStatus::Warning;
+                check.note = Some(
+                    "引用的对象可能由前面没有执行的结构变更创建，无法确定是否有错".to_string(),
+                );
+            } else {
+                check.status = CheckStatus::Error;
+            }
+            check.error = Some(error);
+            client
+                .batch_execute("ROLLBACK TO SAVEPOINT validate_statement")
+                .await
+                .map_err(|e| QueryError::from_pg("回滚到保存点失败", &e))?;
+        }
+    }
+
+    Ok(check)
+}
@@ -0,0 +1,321 @@
+//! SQL 中的命名变量
+//!
+//! 支持两种写法：
+//! - `:name` / `${name}`：值变量，替换为 `$n` 位置参数后绑定
+//! - `${name:ident}`：标识符变量，按标识符引用后直接写入 SQL（如表名、列名）
+//!
+//! 字符串、注释、函数体、`::` 类型转换以及数组下标 `[1:n]` 中的冒号不会被识别为变量。
+
+use crate::error::QueryError;
+use crate::ident::quote_qualified;
+use crate::lexer::{self, Token, TokenKind};
+use crate::params::JsonParam;
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct VariableRef {
+    name: String,
+    identifier: bool,
+    /// 变量文本在 SQL 中的字节范围
+    start: usize,
+    end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VariableInfo {
+    pub name: String,
+    /// 是否为标识符变量
+    pub identifier: bool,
+}
+
+/// 替换变量后的语句
+pub struct BoundSql {
+    pub sql: String,
+    pub params: Vec<JsonParam>,
+    /// 每处替换的位置，用于把错误位置换算回原文
+    replacements: Vec<Replacement>,
+}
+
+/// 一处变量替换，均按字符计
+struct Replacement {
+    bound_start: usize,
+    bound_len: usize,
+    original_start: usize,
+    original_len: usize,
+}
+
+impl BoundSql {
+    /// 把数据库返回的位置（替换后语句中从 1 开始的字符序号）换算为原语句中的位置
+    ///
+    /// 落在替换内容中的位置指向原文中变量的开头。
+    pub fn original_position(&self, position: u32) -> u32 {
+        let position = (position as usize).saturating_sub(1);
+        let mut shift = 0isize;
+        for replacement in &self.replacements {
+            if position < replacement.bound_start {
+                break;
+            }
+            if position < replacement.bound_start + replacement.bound_len {
+                return replacement.original_start as u32 + 1;
+            }
+            shift = (replacement.original_start + replacement.original_len) as isize
+                - (replacement.bound_start + replacement.bound_len) as isize;
+        }
+        (position as isize + shift) as u32 + 1
+    }
+}
+
+fn find_variables(sql: &str) -> Vec<VariableRef> {
+    let tokens = lexer::tokenize(sql);
+    let mut variables = Vec::new();
+    let mut bracket_depth = 0usize;
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let token = tokens[i];
+        match (token.kind, token.text) {
+            (TokenKind::Punct, "[") => bracket_depth += 1,
+            (TokenKind::Punct, "]") => bracket_depth = bracket_depth.saturating_sub(1),
+            (TokenKind::Punct, "$") => {
+                if let Some((variable, consumed)) = braced_variable(&tokens[i..]) {
+                    variables.push(variable);
+                    i += consumed;
+                    continue;
+                }
+            }
+            // `:name`，冒号可能与前面的运算符连在一起，如 `id=:id`
+            (TokenKind::Operator, text)
+                if bracket_depth == 0 && text.ends_with(':') && !text.ends_with("::") =>
+            {
+                if let Some(word) = tokens.get(i + 1).filter(|t| t.kind == TokenKind::Word) {
+                    variables.push(VariableRef {
+                        name: word.text.to_string(),
+                        identifier: false,
+                        start: token.offset + text.len() - 1,
+                        end: word.offset + word.text.len(),
+                    });
+                    i += 2;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    variables
+}
+
+/// 解析 `${name}` 或 `${name:ident}`，返回变量和消耗的词法单元数
+fn braced_variable(tokens: &[Token<'_>]) -> Option<(VariableRef, usize)> {
+    let is = |index: usize, kind: TokenKind, text: Option<&str>| {
+        tokens
+            .get(index)
+            .is_some_and(|t| t.kind == kind && text.is_none_or(|text| t.text == text))
+    };
+
+    if !is(1, TokenKind::Punct, Some("{")) || !is(2, TokenKind::Word, None) {
+        return None;
+    }
+
+    let (identifier, close) = if is(3, TokenKind::Punct, Some("}")) {
+        (false, 3)
+    } else if is(3, TokenKind::Operator, Some(":"))
+        && tokens
+            .get(4)
+            .is_some_and(|t| t.kind == TokenKind::Word && t.text.eq_ignore_ascii_case("ident"))
+        && is(5, TokenKind::Punct, Some("}"))
+    {
+        (true, 5)
+    } else {
+        return None;
+    };
+
+    let variable = VariableRef {
+        name: tokens[2].text.to_string(),
+        identifier,
+        start: tokens[0].offset,
+        end: tokens[close].offset + 1,
+    };
+    Some((variable, close + 1))
+}
+
+/// SQL 中出现的变量，按首次出现的顺序去重
+pub fn list_variables(sql: &str) -> Vec<VariableInfo> {
+    let mut result: Vec<VariableInfo> = Vec::new();
+    for variable in find_variables(sql) {
+        if !result.iter().any(|v| v.name == variable.name) {
+            result.push(VariableInfo {
+                name: variable.name,
+                identifier: variable.identifier,
+            });
+        }
+    }
+    result
+}
+
+/// 未提供取值的变量
+pub fn missing_variables(
+    sql: &str,
+    values: &serde_json::Map<String, serde_json::Value>,
+) -> Vec<VariableInfo> {
+    list_variables(sql)
+        .into_iter()
+        .filter(|v| !values.contains_key(&v.name))
+        .collect()
+}
+
+/// 替换单条语句中的变量：值变量改为 `$n` 参数，同名变量共用一个参数
+pub fn bind_variables(
+    sql: &str,
+    values: &serde_json::Map<String, serde_json::Value>,
+) -> Result<BoundSql, QueryError> {
+    let mut bound = String::with_capacity(sql.len());
+    let mut params = Vec::new();
+    let mut param_names: Vec<&str> = Vec::new();
+    let mut replacements = Vec::new();
+    let mut last = 0;
+
+    let variables = find_variables(sql);
+    for variable in &variables {
+        let value = values
+            .get(&variable.name)
+            .ok_or_else(|| format!("缺少变量: {}", variable.name))?;
+
+        bound.push_str(&sql[last..variable.start]);
+        let bound_start = bound.chars().count();
+        if variable.identifier {
+            let name = value
+                .as_str()
+                .filter(|s| !s.is_empty())
+                .ok_or_else(|| format!("标识符变量 {} 必须是非空字符串", variable.name))?;
+            bound.push_str(&quote_qualified(name));
+        } else {
+            let index = match param_names.iter().position(|n| *n == variable.name) {
+                Some(index) => index,
+                None => {
+                    param_names.push(&variable.name);
+                    params.push(JsonParam(value.clone()));
+                    params.len() - 1
+                }
+            };
+            bound.push_str(&format!("${}", index + 1));
+        }
+        replacements.push(Replacement {
+            bound_start,
+            bound_len: bound.chars().count() - bound_start,
+            original_start: sql[..variable.start].chars().count(),
+            original_len: sql[variable.start..variable.end].chars().count(),
+        });
+        last = variable.end;
+    }
+    bound.push_str(&sql[last..]);
+
+    Ok(BoundSql {
+        sql: bound,
+        params,
+        replacements,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn values(value: serde_json::Value) -> serde_json::Map<String, serde_json::Value> {
+        value.as_object().cloned().unwrap()
+    }
+
+    fn names(sql: &str) -> Vec<String> {
+        list_variables(sql).into_iter().map(|v| v.name).collect()
+    }
+
+    #[test]
+    fn finds_both_forms_once() {
+        let sql = "SELECT * FROM ${table:ident} WHERE id=:id OR parent = ${id} AND name = :name";
+        let variables = list_variables(sql);
+        assert_eq!(names(sql), ["table", "id", "name"]);
+        assert!(variables[0].identifier);
+        assert!(!variables[1].identifier);
+    }
+
+    #[test]
+    fn ignores_casts_strings_comments_and_slices() {
+        let sql = "SELECT ':a', \"b:c\", x::text, arr[1:n], $$ :d $$ -- :e\n/* :f */ FROM t";
+        assert!(names(sql).is_empty());
+    }
+
+    #[test]
+    fn binds_values_as_params() {
+        let bound = bind_variables(
+            "SELECT * FROM t WHERE a = :a AND b = :b OR c = :a",
+            &values(json!({"a": 1, "b": "x"})),
+        )
+        .unwrap();
+        assert_eq!(
+            bound.sql,
+            "SELECT * FROM t WHERE a = $1 AND b = $2 OR c = $1"
+        );
+        assert_eq!(bound.params.len(), 2);
+        assert_eq!(bound.params[1].0, json!("x"));
+    }
+
+    #[test]
+    fn quotes_identifier_variables() {
+        let bound = bind_variables(
+            "SELECT * FROM ${table:ident}",
+            &values(json!({"table": "my table"})),
+        )
+        .unwrap();
+        assert_eq!(bound.sql, "SELECT * FROM \"my table\"");
+        assert!(bound.params.is_empty());
+
+        let error = bind_variables("SELECT ${col:ident}", &values(json!({"col": ""})));
+        assert!(error.is_err());
+    }
+
+    #[test]
+    fn maps_positions_back_to_the_original() {
+        let sql = "SELECT * FROM ${t:ident} WHERE é = :value AND x = y";
+        let bound = bind_variables(sql, &values(json!({"t": "my table", "value": 1}))).unwrap();
+        assert_eq!(
+            bound.sql,
+            "SELECT * FROM \"my table\" WHERE é = $1 AND x = y"
+        );
+        let position = |needle: &str, text: &str| {
+            text[..text.find(needle).unwrap()].chars().count() as u32 + 1
+        };
+        // 替换之前、之间和之后的位置
+        assert_eq!(
+            bound.original_position(position("FROM", &bound.sql)),
+            position("FROM", sql)
+        );
+        assert_eq!(
+            bound.original_position(position("WHERE", &bound.sql)),
+            position("WHERE", sql)
+        );
+        assert_eq!(
+            bound.original_position(position("AND", &bound.sql)),
+            position("AND", sql)
+        );
+        // 落在替换内容中时指向变量开头
+        assert_eq!(
+            bound.original_position(position("$1", &bound.sql)),
+            position(":value", sql)
+        );
+        assert_eq!(
+            bound.original_position(position("table", &bound.sql)),
+            position("${t", sql)
+        );
+    }
+
+    #[test]
+    fn reports_missing_variables() {
+        let sql = "SELECT :a, :b";
+        let missing = missing_variables(sql, &values(json!({"a": 1})));
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].name, "b");
+        assert!(bind_variables(sql, &values(json!({"a": 1}))).is_err());
+    }
+}
@@ -18,6 +18,31 @@ interface ConnectionWithDBs extends ConnectionConfig {
   expanded?: boolean;
 }
 
+// 后端返回的结构化查询错误
+interface QueryError {
+  context?: string;
+  code?: string | null;
+  message: string;
+  detail?: string | null;
+  hint?: string | null;
+  position?: { offset: number; line: number; column: number } | null;
+  timeout?: "statement" | "lock";
+}
+
+const isQueryError = (error: unknown): error is QueryError =>
+  typeof error === "object" && error !== null && "message" in error;
+
+// 将结构化错误格式化为展示文本
+const formatQueryError = (error: QueryError) => {
+  let text = error.context ? `${error.context}: ${error.message}` : error.message;
+  if (error.timeout) text = `${error.timeout === "lock" ? "等锁超时" : "查询超时"} - ${text}`;
+  if (error.code) text += ` [${error.code}]`;
+  if (error.position) text += `（第 ${error.position.line} 行，第 ${error.position.column} 列）`;
+  if (error.detail) text += `\n详情: ${error.detail}`;
+  if (error.hint) text += `\n提示: ${error.hint}`;
+  return text;
+};
+
 function App() {
   // 状态管理
   const [connections, setConnections] = useState<ConnectionWithDBs[]>([]);
@@ -340,7 +365,14 @@ function App() {
 
       setQueryResult({
         data: [],
-        error: error instanceof Error ? error.message : "查询执行失败，请检查查询格式",
+        error: error instanceof Error
+          ? error.message
+          : isQueryError(error)
+            ? formatQueryError(error)
+            : typeof error === "string"
+              ? error
+              : "查询执行失败，请检查查询格式",
+        errorDetail: isQueryError(error) ? error : undefined,
         duration: duration,
       });
     } finally {